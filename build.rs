@@ -0,0 +1,11 @@
+use std::env;
+
+fn main() {
+    // registered fonts are loaded through pango's fontconfig font map, which is not bound
+    // by the pango crates; fontconfig itself is linked by its own bindings
+    if env::var_os("CARGO_FEATURE_FONTCONFIG").is_some() {
+        if let Err(e) = pkg_config::probe_library("pangoft2") {
+            panic!("the fontconfig feature needs pangoft2, or build without default features to leave it out: {}", e);
+        }
+    }
+}
@@ -1,14 +1,17 @@
 use std::error::Error;
 use std::fmt::{self, Display};
-use std::num::ParseIntError;
+use std::num::{ParseIntError, ParseFloatError};
 use std::str::Utf8Error;
 use std::string::FromUtf8Error;
 use std::ffi::NulError;
+use std::io::Error as IoError;
 use glib::Error as GlibError;
-use cairo::StreamWithError;
+use cairo::{StreamWithError, Status as CairoStatus, IoError as CairoIoError};
 use std::any::Any;
 use libxml::parser::XmlParseError;
 use serde_json::Error as SerdeJsonError;
+use kurbo::SvgParseError;
+use crate::layout::FitReport;
 
 #[derive(Debug)]
 pub enum SvgTextBoxError {
@@ -18,12 +21,18 @@ pub enum SvgTextBoxError {
     NoValidHeights,
     /// No valid font sizes were given
     NoValidFontSizes,
-    /// Could not work out a way to make all the requirements for sizing match up
+    /// None of the possible dimensions could fit the text; the report explains why
+    FitFailed(FitReport),
+    /// Could not work out a way to make all the requirements for sizing match up.
+    /// Nothing gives this any more; a fit which fails gives `FitFailed`, with its report.
+    #[deprecated(note = "a fit which fails gives `FitFailed`, with a report of what was tried")]
     CouldNotFit,
     // We tried to unwrap an option we thought was some
     UnexpectedNone,
     /// Tried to create an alignment from an invalid string
     InvalidAlignment,
+    /// Baseline alignment was asked for without saying where the baseline is
+    MissingBaseline,
     Utf8Error(Utf8Error),
     FromUtf8Error(FromUtf8Error),
     PCSWhitespace,
@@ -31,11 +40,45 @@ pub enum SvgTextBoxError {
     GlibErr(GlibError),
     MissingMarkup,
     BadIntParse(ParseIntError),
+    BadFloatParse(ParseFloatError),
+    /// A value which should be true or false was neither
+    BadBoolParse(String),
+    /// An aspect ratio was not a positive, finite number
+    InvalidAspectRatio(f64),
+    /// A png scale was not a positive, finite number
+    InvalidScale(f64),
+    /// Tried to create a length from an invalid value or unit
+    InvalidLength(String),
+    /// There are no hyphenation patterns for this language
+    UnsupportedLanguage(String),
+    /// The pango linked against is too old for something which was asked for
+    PangoTooOld(String),
+    /// A font file or directory could not be loaded
+    FontFile(String),
+    /// Fonts were registered, but this build can't load them without fontconfig
+    FontsUnsupported,
+    /// An svg path could not be parsed, or does not describe a usable outline
+    InvalidPath(String),
+    /// Attributes were given which cannot be used together
+    ConflictingAttributes(String),
+    /// An element referenced by id is missing, or cannot be flowed around
+    BadReference(String),
     NulError(NulError),
-    CairoError(StreamWithError),
-    Any(Box<dyn Any>),
+    /// Cairo could not write to its output stream
+    CairoError(IoError),
+    CairoStatus(CairoStatus),
+    /// Cairo's output stream did not hold the bytes it was given
+    OutputStream,
+    CairoIoError(CairoIoError),
+    /// Reading or writing a file failed
+    Io(IoError),
+    Any(Box<dyn Any + Send>),
     NSError,
     StackedTextboxes,
+    /// A document was requested without any textboxes to put in it
+    NoPages,
+    /// Several pages were requested in a format which only holds one
+    PagesUnsupported,
     XmlParseError(XmlParseError),
     XmlNoRoot,
     SerdeJsonError(SerdeJsonError),
@@ -55,7 +98,10 @@ impl Error for SvgTextBoxError {
             SvgTextBoxError::Utf8Error(e) => Some(e),
             SvgTextBoxError::GlibErr(e) => Some(e),
             SvgTextBoxError::BadIntParse(e) => Some(e),
+            SvgTextBoxError::BadFloatParse(e) => Some(e),
             SvgTextBoxError::NulError(e) => Some(e),
+            SvgTextBoxError::Io(e) => Some(e),
+            SvgTextBoxError::CairoError(e) => Some(e),
             _ => None
         }
     }
@@ -73,6 +119,12 @@ impl From<ParseIntError> for SvgTextBoxError {
     }
 }
 
+impl From<ParseFloatError> for SvgTextBoxError {
+    fn from(e: ParseFloatError) -> Self {
+        SvgTextBoxError::BadFloatParse(e)
+    }
+}
+
 impl From<NulError> for SvgTextBoxError {
     fn from(e: NulError) -> Self {
         SvgTextBoxError::NulError(e)
@@ -87,7 +139,26 @@ impl From<GlibError> for SvgTextBoxError {
 
 impl From<StreamWithError> for SvgTextBoxError {
     fn from(e: StreamWithError) -> Self {
-        SvgTextBoxError::CairoError(e)
+        // the stream is dropped, so that errors can be sent between threads
+        SvgTextBoxError::CairoError(e.error)
+    }
+}
+
+impl From<CairoStatus> for SvgTextBoxError {
+    fn from(e: CairoStatus) -> Self {
+        SvgTextBoxError::CairoStatus(e)
+    }
+}
+
+impl From<CairoIoError> for SvgTextBoxError {
+    fn from(e: CairoIoError) -> Self {
+        SvgTextBoxError::CairoIoError(e)
+    }
+}
+
+impl From<IoError> for SvgTextBoxError {
+    fn from(e: IoError) -> Self {
+        SvgTextBoxError::Io(e)
     }
 }
 
@@ -97,12 +168,18 @@ impl From<FromUtf8Error> for SvgTextBoxError {
     }
 }
 
-impl From<Box<dyn Any>> for SvgTextBoxError {
-    fn from(e: Box<dyn Any>) -> Self {
+impl From<Box<dyn Any + Send>> for SvgTextBoxError {
+    fn from(e: Box<dyn Any + Send>) -> Self {
         SvgTextBoxError::Any(e)
     }
 }
 
+impl From<SvgParseError> for SvgTextBoxError {
+    fn from(e: SvgParseError) -> Self {
+        SvgTextBoxError::InvalidPath(e.to_string())
+    }
+}
+
 impl From<XmlParseError> for SvgTextBoxError {
     fn from(e: XmlParseError) -> Self {
         SvgTextBoxError::XmlParseError(e)
@@ -0,0 +1,483 @@
+use crate::errors::SvgTextBoxError;
+#[cfg(feature = "fontconfig")]
+use fontconfig_sys::{FcChar8, FcConfig, FcResultMatch, FcSetApplication, FcSetSystem};
+#[cfg(feature = "fontconfig")]
+use fontconfig_sys::{FcConfigAppFontAddDir, FcConfigAppFontAddFile, FcConfigCreate, FcConfigDestroy, FcConfigGetFonts, FcInitLoadConfigAndFonts};
+#[cfg(feature = "fontconfig")]
+use fontconfig_sys::{FcFontSetAdd, FcFreeTypeQuery, FcPatternAddString, FcPatternDestroy, FcPatternGetString};
+#[cfg(feature = "fontconfig")]
+use fontconfig_sys::constants::{FC_FAMILY, FC_FILE};
+#[cfg(feature = "fontconfig")]
+use glib::translate::ToGlibPtr;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use siphasher::sip::SipHasher13;
+use std::convert::TryFrom;
+use std::fs;
+use std::hash::Hasher;
+#[cfg(feature = "fontconfig")]
+use std::ffi::{CStr, CString};
+#[cfg(feature = "fontconfig")]
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+#[cfg(feature = "fontconfig")]
+use std::ptr;
+
+#[cfg(feature = "fontconfig")]
+extern "C" {
+    // from pangoft2, which the pango 0.7 crates do not bind; it is found by the build script.
+    // The font map takes its own reference to the config.
+    fn pango_fc_font_map_set_config(fontmap: *mut pango_sys::PangoFontMap, config: *mut FcConfig);
+}
+
+/// How many font maps each thread keeps before dropping the least recently used
+const FONT_MAP_LIMIT: usize = 8;
+
+thread_local! {
+    /// Font maps can't be shared between threads, so each thread builds its own for each registry,
+    /// along with the fingerprint of the files it was built from.
+    /// The most recently used is last.
+    static FONT_MAPS: RefCell<Vec<(FontRegistry, u64, pango::FontMap)>> = RefCell::new(Vec::new());
+}
+
+/// Font files and directories to use as well as the installed fonts, so that
+/// text can be set in fonts which are not installed.
+/// Families in the files can be named by their own names, or by an alias.
+///
+/// This needs pango to be using fontconfig, as it does on Linux, and the `fontconfig` feature,
+/// which is on by default. Without the feature, a registry with fonts can't be deserialized,
+/// and `font_map` fails with `FontsUnsupported`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "FontEntries", into = "FontEntries")]
+pub struct FontRegistry {
+    /// font files, or directories of them
+    paths: Vec<PathBuf>,
+    /// names which stand for the family in a font file
+    aliases: BTreeMap<String, PathBuf>,
+    /// leave out the installed fonts, so that text looks the same on every machine
+    only_registered: bool,
+}
+
+/// A font file or directory, optionally with an alias for the family in it
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum FontEntry {
+    Path(PathBuf),
+    Alias(BTreeMap<String, PathBuf>),
+}
+
+/// Either a list of fonts, a map of aliases to font files,
+/// or a list of fonts which are to be used without the installed fonts
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum FontEntries {
+    List(Vec<FontEntry>),
+    Aliases(BTreeMap<String, PathBuf>),
+    Registry {
+        fonts: Vec<FontEntry>,
+        #[serde(alias = "only-registered", default)]
+        only_registered: bool,
+    },
+}
+
+impl TryFrom<FontEntries> for FontRegistry {
+    type Error = String;
+
+    fn try_from(entries: FontEntries) -> Result<Self, Self::Error> {
+        let mut registry = FontRegistry::new();
+        let entries = match entries {
+            FontEntries::List(l) => l,
+            FontEntries::Aliases(a) => vec![FontEntry::Alias(a)],
+            FontEntries::Registry {fonts, only_registered} => {
+                registry.set_only_registered(only_registered);
+                fonts
+            }
+        };
+        for entry in entries {
+            match entry {
+                FontEntry::Path(p) => {
+                    registry.add_path(p);
+                },
+                FontEntry::Alias(a) => {
+                    for (alias, p) in a {
+                        registry.add_alias(&alias, p);
+                    }
+                }
+            }
+        }
+        if !cfg!(feature = "fontconfig") && !registry.is_empty() {
+            return Err("registered fonts need svgtextbox to be built with the fontconfig feature".to_string());
+        }
+        Ok(registry)
+    }
+}
+
+impl From<FontRegistry> for FontEntries {
+    fn from(registry: FontRegistry) -> Self {
+        let mut entries = registry.paths.into_iter()
+            .map(FontEntry::Path)
+            .collect::<Vec<FontEntry>>();
+        if !registry.aliases.is_empty() {
+            entries.push(FontEntry::Alias(registry.aliases));
+        }
+        if registry.only_registered {
+            FontEntries::Registry {fonts: entries, only_registered: true}
+        } else {
+            FontEntries::List(entries)
+        }
+    }
+}
+
+impl FontRegistry {
+
+    pub fn new() -> Self {
+        FontRegistry::default()
+    }
+
+    /// Use the fonts in a font file, or in every font file in a directory
+    pub fn add_path<P: Into<PathBuf>>(&mut self, path: P) -> &mut Self {
+        let path = path.into();
+        if !self.paths.contains(&path) {
+            self.paths.push(path);
+        }
+        self
+    }
+
+    /// Use the font in `path`, which can also be called `alias` in font descriptions and markup
+    pub fn add_alias<P: Into<PathBuf>>(&mut self, alias: &str, path: P) -> &mut Self {
+        self.aliases.insert(alias.to_string(), path.into());
+        self
+    }
+
+    /// Use only the fonts in this registry, rather than adding them to the installed fonts
+    pub fn set_only_registered(&mut self, only_registered: bool) -> &mut Self {
+        self.only_registered = only_registered;
+        self
+    }
+
+    /// Resolve relative font paths against `dir` rather than the working directory
+    pub fn resolve_paths(&mut self, dir: &Path) -> &mut Self {
+        for path in self.paths.iter_mut().chain(self.aliases.values_mut()) {
+            if path.is_relative() {
+                *path = dir.join(path.as_path());
+            }
+        }
+        self
+    }
+
+    /// Whether this registry changes nothing, leaving text in the installed fonts
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty() && self.aliases.is_empty() && !self.only_registered
+    }
+
+    /// A font map which has the fonts in this registry, and the installed fonts unless
+    /// only registered fonts are to be used.
+    /// Each thread gets its own, which is built the first time it is asked for,
+    /// and again whenever the registered files change.
+    /// Only the few most recently used are kept.
+    pub fn font_map(&self) -> Result<pango::FontMap, SvgTextBoxError> {
+        let fingerprint = self.fingerprint();
+        let existing = FONT_MAPS.with(|maps| {
+            let mut maps = maps.borrow_mut();
+            let i = maps.iter().position(|(registry, _, _)| registry == self)?;
+            // a map built from files which have since changed is never used again
+            let entry = maps.remove(i);
+            if entry.1 == fingerprint {
+                let font_map = entry.2.clone();
+                maps.push(entry);
+                Some(font_map)
+            } else {
+                None
+            }
+        });
+        if let Some(font_map) = existing {
+            return Ok(font_map);
+        }
+        let font_map = self.create_font_map()?;
+        FONT_MAPS.with(|maps| {
+            let mut maps = maps.borrow_mut();
+            if maps.len() >= FONT_MAP_LIMIT {
+                maps.remove(0);
+            }
+            maps.push((self.clone(), fingerprint, font_map.clone()));
+        });
+        Ok(font_map)
+    }
+
+    /// A hash of the size and modification time of every registered font file,
+    /// including those in registered directories, so that replacing a file changes it
+    pub fn fingerprint(&self) -> u64 {
+        // fixed keys, since the fingerprint is part of the keys a `FitCache` saves
+        let mut hasher = SipHasher13::new_with_keys(0, 0);
+        for path in self.paths.iter().chain(self.aliases.values()) {
+            hash_files(path, &mut hasher);
+        }
+        hasher.finish()
+    }
+
+    #[cfg(not(feature = "fontconfig"))]
+    fn create_font_map(&self) -> Result<pango::FontMap, SvgTextBoxError> {
+        Err(SvgTextBoxError::FontsUnsupported)
+    }
+
+    #[cfg(feature = "fontconfig")]
+    fn create_font_map(&self) -> Result<pango::FontMap, SvgTextBoxError> {
+        let font_map = pangocairo::FontMap::new_for_font_type(cairo::FontType::FontTypeFt)
+            .ok_or(SvgTextBoxError::UnexpectedNone)?;
+        unsafe {
+            let config = if self.only_registered {
+                FcConfigCreate()
+            } else {
+                FcInitLoadConfigAndFonts()
+            };
+            if config.is_null() {
+                return Err(SvgTextBoxError::UnexpectedNone);
+            }
+            let loaded = self.load(config);
+            if loaded.is_ok() {
+                pango_fc_font_map_set_config(font_map.to_glib_none().0, config);
+            }
+            FcConfigDestroy(config);
+            loaded?;
+        }
+        Ok(font_map)
+    }
+
+    /// Add the fonts and aliases of this registry to `config`
+    #[cfg(feature = "fontconfig")]
+    unsafe fn load(&self, config: *mut FcConfig) -> Result<(), SvgTextBoxError> {
+        for path in self.paths.iter().chain(self.aliases.values()) {
+            let c_path = c_path(path)?;
+            let added = if path.is_dir() {
+                FcConfigAppFontAddDir(config, c_path.as_ptr() as *const FcChar8)
+            } else {
+                FcConfigAppFontAddFile(config, c_path.as_ptr() as *const FcChar8)
+            };
+            if added == 0 {
+                return Err(SvgTextBoxError::FontFile(path.display().to_string()));
+            }
+        }
+        for (alias, path) in self.aliases.iter() {
+            add_alias(config, alias, path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Hash the path, size and modification time of `path`, and of everything inside it if it is
+/// a directory, in a fixed order. Files which can't be read are hashed by their path alone.
+/// Only bytes are written to `hasher`, so that with a fixed hasher the hash is the same in every build.
+pub(crate) fn hash_files(path: &Path, hasher: &mut impl Hasher) {
+    hasher.write(path.to_string_lossy().as_bytes());
+    hasher.write(&[0]);
+    let metadata = match fs::metadata(path) {
+        Ok(m) => m,
+        Err(_) => return
+    };
+    if metadata.is_dir() {
+        let mut entries = fs::read_dir(path)
+            .map(|dir| dir.filter_map(|e| e.ok().map(|e| e.path())).collect::<Vec<PathBuf>>())
+            .unwrap_or_default();
+        entries.sort();
+        for entry in entries {
+            hash_files(&entry, hasher);
+        }
+    } else {
+        let modified = metadata.modified().ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        hasher.write(&metadata.len().to_le_bytes());
+        hasher.write(&modified.as_secs().to_le_bytes());
+        hasher.write(&modified.subsec_nanos().to_le_bytes());
+    }
+}
+
+/// The files of the fonts fontconfig has installed, in order
+#[cfg(feature = "fontconfig")]
+pub(crate) fn installed_font_files() -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    unsafe {
+        // the current config, which is what the default font map uses
+        let fonts = FcConfigGetFonts(ptr::null_mut(), FcSetSystem);
+        if fonts.is_null() {
+            return files;
+        }
+        for i in 0..(*fonts).nfont as isize {
+            let mut file: *mut FcChar8 = ptr::null_mut();
+            if FcPatternGetString(*(*fonts).fonts.offset(i), FC_FILE.as_ptr(), 0, &mut file) == FcResultMatch {
+                let file = CStr::from_ptr(file as *const c_char);
+                files.push(PathBuf::from(file.to_string_lossy().into_owned()));
+            }
+        }
+    }
+    files.sort();
+    files.dedup();
+    files
+}
+
+/// Without fontconfig the installed font files can't be found
+#[cfg(not(feature = "fontconfig"))]
+pub(crate) fn installed_font_files() -> Vec<PathBuf> {
+    Vec::new()
+}
+
+#[cfg(feature = "fontconfig")]
+fn c_path(path: &Path) -> Result<CString, SvgTextBoxError> {
+    let s = path.to_str()
+        .ok_or_else(|| SvgTextBoxError::FontFile(path.display().to_string()))?;
+    Ok(CString::new(s)?)
+}
+
+/// Add each font in the file at `path` to the application fonts of `config` again,
+/// with `alias` as another name for its family
+#[cfg(feature = "fontconfig")]
+unsafe fn add_alias(config: *mut FcConfig, alias: &str, path: &Path) -> Result<(), SvgTextBoxError> {
+    let c_path = c_path(path)?;
+    let c_alias = CString::new(alias)?;
+    let not_a_font = || SvgTextBoxError::FontFile(path.display().to_string());
+    // the file has already been added, so the set of application fonts exists
+    let fonts = FcConfigGetFonts(config, FcSetApplication);
+    if fonts.is_null() {
+        return Err(not_a_font());
+    }
+    let mut count = 1;
+    let mut id = 0;
+    while id < count {
+        let pattern = FcFreeTypeQuery(c_path.as_ptr() as *const FcChar8, id, ptr::null_mut(), &mut count);
+        if pattern.is_null() {
+            return Err(not_a_font());
+        }
+        // once added, the pattern belongs to the set
+        let aliased = FcPatternAddString(pattern, FC_FAMILY.as_ptr(), c_alias.as_ptr() as *const FcChar8) != 0;
+        if !aliased || FcFontSetAdd(fonts, pattern) == 0 {
+            FcPatternDestroy(pattern);
+            return Err(not_a_font());
+        }
+        id += 1;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn font_registry_serde() {
+        let r: FontRegistry = serde_json::from_str(r#"["fonts/", {"brand": "fonts/Brand.otf"}]"#).unwrap();
+        let mut expected = FontRegistry::new();
+        expected.add_path("fonts/")
+            .add_alias("brand", "fonts/Brand.otf");
+        assert_eq!(r, expected);
+        let r: FontRegistry = serde_json::from_str(r#"{"brand": "fonts/Brand.otf"}"#).unwrap();
+        assert_eq!(r.aliases.len(), 1);
+        let round_trip: FontRegistry = serde_json::from_str(&serde_json::to_string(&expected).unwrap()).unwrap();
+        assert_eq!(round_trip, expected);
+        let r: FontRegistry = serde_json::from_str(r#"{"fonts": ["fonts/"], "only-registered": true}"#).unwrap();
+        let mut expected = FontRegistry::new();
+        expected.add_path("fonts/")
+            .set_only_registered(true);
+        assert_eq!(r, expected);
+        let round_trip: FontRegistry = serde_json::from_str(&serde_json::to_string(&expected).unwrap()).unwrap();
+        assert_eq!(round_trip, expected);
+        // leaving out the installed fonts is a change even without any fonts of its own
+        let r: FontRegistry = serde_json::from_str(r#"{"fonts": [], "only-registered": true}"#).unwrap();
+        assert!(!r.is_empty());
+        assert!(FontRegistry::new().is_empty());
+    }
+
+    #[test]
+    fn fingerprint_changes_with_files() {
+        let fonts = std::env::temp_dir().join(format!("svgtextbox-fingerprint-{}", std::process::id()));
+        std::fs::create_dir_all(&fonts).unwrap();
+        let mut r = FontRegistry::new();
+        r.add_path(&fonts);
+        let empty = r.fingerprint();
+        assert_eq!(empty, r.fingerprint());
+        std::fs::write(fonts.join("Brand.otf"), b"not really a font").unwrap();
+        let one_file = r.fingerprint();
+        assert_ne!(empty, one_file);
+        std::fs::write(fonts.join("Brand.otf"), b"a different font altogether").unwrap();
+        assert_ne!(one_file, r.fingerprint());
+        std::fs::remove_dir_all(&fonts).unwrap();
+    }
+
+    #[test]
+    fn resolve_font_paths() {
+        let mut r = FontRegistry::new();
+        r.add_path("fonts/")
+            .add_path("/usr/share/fonts/")
+            .add_alias("brand", "Brand.otf")
+            .resolve_paths(Path::new("/docs"));
+        let mut expected = FontRegistry::new();
+        expected.add_path("/docs/fonts/")
+            .add_path("/usr/share/fonts/")
+            .add_alias("brand", "/docs/Brand.otf");
+        assert_eq!(r, expected);
+    }
+
+    #[test]
+    #[cfg(feature = "fontconfig")]
+    fn missing_font_file() {
+        let mut r = FontRegistry::new();
+        r.add_path("/no/such/font.otf");
+        match r.font_map() {
+            Err(SvgTextBoxError::FontFile(p)) => assert_eq!(p, "/no/such/font.otf"),
+            _ => panic!("expected the font file to be missing")
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "fontconfig")]
+    fn alias_needs_a_font() {
+        let file = std::env::temp_dir().join(format!("svgtextbox-alias-{}.otf", std::process::id()));
+        std::fs::write(&file, b"not really a font").unwrap();
+        let mut r = FontRegistry::new();
+        r.add_alias("brand", &file);
+        match r.font_map() {
+            Err(SvgTextBoxError::FontFile(p)) => assert_eq!(p, file.display().to_string()),
+            _ => panic!("expected the aliased file to be refused")
+        }
+        std::fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    #[cfg(not(feature = "fontconfig"))]
+    fn fonts_unsupported() {
+        assert!(serde_json::from_str::<FontRegistry>(r#"["fonts/"]"#).is_err());
+        assert!(serde_json::from_str::<FontRegistry>("[]").unwrap().is_empty());
+        let mut r = FontRegistry::new();
+        r.add_path("fonts/");
+        match r.font_map() {
+            Err(SvgTextBoxError::FontsUnsupported) => (),
+            _ => panic!("expected registered fonts to need the fontconfig feature")
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "fontconfig")]
+    fn font_map_per_registry() {
+        let fonts = std::env::temp_dir().join(format!("svgtextbox-fonts-{}", std::process::id()));
+        std::fs::create_dir_all(&fonts).unwrap();
+        let mut r = FontRegistry::new();
+        r.add_path(&fonts);
+        let first = r.font_map().unwrap();
+        let second = r.font_map().unwrap();
+        assert_eq!(first, second);
+        assert_ne!(first, FontRegistry::new().font_map().unwrap());
+        r.set_only_registered(true);
+        assert_ne!(first, r.font_map().unwrap());
+        // only the most recently used maps are kept
+        for i in 0..FONT_MAP_LIMIT {
+            let dir = fonts.join(i.to_string());
+            std::fs::create_dir_all(&dir).unwrap();
+            FontRegistry::new().add_path(dir).font_map().unwrap();
+        }
+        assert_eq!(FONT_MAPS.with(|maps| maps.borrow().len()), FONT_MAP_LIMIT);
+        r.set_only_registered(false);
+        assert_ne!(first, r.font_map().unwrap());
+        std::fs::remove_dir_all(&fonts).unwrap();
+    }
+}
@@ -0,0 +1,104 @@
+use crate::errors::SvgTextBoxError;
+use hypher::Lang;
+
+/// Where pango may break a word, showing a hyphen if it does
+pub(crate) static SOFT_HYPHEN: char = '\u{ad}';
+
+/// The hyphenation patterns for a language tag such as `de` or `nl-BE`
+pub(crate) fn patterns(tag: &str) -> Result<Lang, SvgTextBoxError> {
+    let primary = tag.split(|c| c == '-' || c == '_')
+        .next()
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let code = match primary.as_bytes() {
+        [a, b] => [*a, *b],
+        _ => return Err(SvgTextBoxError::UnsupportedLanguage(tag.to_string()))
+    };
+    Lang::from_iso(code)
+        .ok_or_else(|| SvgTextBoxError::UnsupportedLanguage(tag.to_string()))
+}
+
+#[derive(Clone, Copy)]
+enum Position {
+    Text,
+    /// inside a tag, possibly within a quoted attribute value
+    Tag(Option<char>),
+    Entity,
+}
+
+/// `markup` with a soft hyphen between the syllables of each word.
+/// Tags and entities are left untouched, so valid markup stays valid.
+pub(crate) fn hyphenate_markup(markup: &str, lang: Lang) -> String {
+    let mut hyphenated = String::with_capacity(markup.len() * 5 / 4);
+    let mut word = String::new();
+    let mut position = Position::Text;
+    for c in markup.chars() {
+        position = match position {
+            Position::Text => {
+                if c.is_alphabetic() {
+                    word.push(c);
+                    continue;
+                }
+                push_word(&mut hyphenated, &mut word, lang);
+                match c {
+                    '<' => Position::Tag(None),
+                    '&' => Position::Entity,
+                    _ => Position::Text
+                }
+            },
+            Position::Tag(None) => match c {
+                '>' => Position::Text,
+                '"' | '\'' => Position::Tag(Some(c)),
+                _ => position
+            },
+            Position::Tag(Some(q)) if q == c => Position::Tag(None),
+            Position::Entity if c == ';' => Position::Text,
+            _ => position
+        };
+        hyphenated.push(c);
+    }
+    push_word(&mut hyphenated, &mut word, lang);
+    hyphenated
+}
+
+fn push_word(hyphenated: &mut String, word: &mut String, lang: Lang) {
+    if word.is_empty() {
+        return;
+    }
+    let syllables = hypher::hyphenate(word, lang)
+        .join(&SOFT_HYPHEN.to_string());
+    hyphenated.push_str(&syllables);
+    word.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn language_tags() {
+        assert_eq!(patterns("de").unwrap(), Lang::German);
+        assert_eq!(patterns("nl-BE").unwrap(), Lang::Dutch);
+        assert_eq!(patterns("EN_gb").unwrap(), Lang::English);
+        assert!(patterns("xx").is_err());
+        assert!(patterns("deu").is_err());
+    }
+
+    #[test]
+    fn hyphenate_words() {
+        let hyphenated = hyphenate_markup("Donaudampfschifffahrt", Lang::German);
+        assert!(hyphenated.contains(SOFT_HYPHEN));
+        assert_eq!(hyphenated.replace(SOFT_HYPHEN, ""), "Donaudampfschifffahrt");
+    }
+
+    #[test]
+    fn markup_preserved() {
+        let markup = r#"<span font_family="Hyphenation">Verzekeringsmaatschappij</span> &amp; <b>ziektekostenverzekering</b>"#;
+        let hyphenated = hyphenate_markup(markup, Lang::Dutch);
+        assert!(hyphenated.starts_with(r#"<span font_family="Hyphenation">"#));
+        assert!(hyphenated.contains("</span> &amp; <b>"));
+        assert!(hyphenated.ends_with("</b>"));
+        assert_eq!(hyphenated.replace(SOFT_HYPHEN, ""), markup);
+        assert!(pango::parse_markup(&hyphenated, '\u{0}').is_ok());
+    }
+}
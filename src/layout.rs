@@ -65,157 +65,235 @@
 use std::collections::BTreeSet;
 use crate::errors::SvgTextBoxError;
 use std::iter;
-use pango::{Layout, EllipsizeMode, WrapMode, FontMapExt};
+use pango::{Layout, EllipsizeMode, WrapMode, FontMapExt, AttrList, Attribute, Direction, Gravity, GravityHint, Language};
 use pangocairo::FontMap;
 use std::cmp::Ordering;
-pub use interface::{LayoutSource, RenderedTextbox};
-
-
-mod interface {
-	use crate::errors::SvgTextBoxError;
-	use std::collections::{HashMap, BTreeMap};
-	use pango::{SCALE, Alignment, FontDescription};
-	use super::LayoutManager;
-
-	/// An implementation of this trait can be used to generate a layout
-	pub trait LayoutSource {
-	    /// All possible font sizes, where the unit is `points * pango::SCALE`
-	    /// This should be ordered.
-	    fn possible_font_sizes<'a>(&'a self) -> Box<dyn Iterator<Item=i32> + 'a>;
-	    /// All possible heights (in order of preference); the unit should be `points * pango::SCALE`
-	    fn possible_heights<'a>(&'a self) -> Box<dyn Iterator<Item=i32> + 'a>;
-	    /// All possible widths (in order of preference); the unit should be `points * pango::SCALE`
-	    fn possible_widths<'a>(&'a self) -> Box<dyn Iterator<Item=i32> + 'a>;
-	    /// The font description of the text as a whole
-	    fn font_description(&self) -> &FontDescription;
-	    /// The text to set
-	    fn markup(&self) -> &str;
-	    /// The alignment of the text
-	    fn alignment(&self) -> Alignment;
-	    // the image output width as distinct from the textbox width (defaults to textbox width)
-	    fn output_width(&self, layout_width: i32) -> f64 {
-	        f64::from(layout_width / SCALE)
-	    }
-	    /// the image output height as distinct from the textbox height (defaults to textbox height)
-	    fn output_height(&self, layout_height: i32) -> f64 {
-	        f64::from(layout_height / SCALE)
-	    }
-	    /// the x-coordinate to place the textbox on the surface (defaults to 0.0)
-	    fn output_x(&self) -> f64 {
-	        0.0
-	    }
-	    /// the y-coordinate to place the textbox on the surface (defaults to 0.0)
-	    fn output_y(&self) -> f64 {
-	        0.0
-	    }
-	    /// whether to shift the rendered layout on the image surface to vertically center if
-	    /// if the layout does not fill the space available (defaults to true)
-	    fn centre_output_vertically(&self) -> bool {
-	        true
-	    }
+#[cfg(feature = "v1_44")]
+use glib::translate::ToGlibPtr;
+use crate::hyphenation::{hyphenate_markup, patterns};
+use crate::fonts::FontRegistry;
+use shape::{flow, set_in_shape, Region};
+pub use interface::{LayoutSource, RenderedTextbox, RenderedPng, PngOptions, RenderedDocument, OutputFormat};
+pub use report::{FitReport, DimensionAttempt, FitFailure, FitFailureReason, Truncation};
+pub use chain::RenderedChain;
+pub use live_text::SvgTextMode;
+pub use vertical::VerticalAlignment;
+pub use overflow::OverflowPolicy;
+pub use orientation::{WritingMode, TextDirection};
+use orientation::Orientation;
+pub use shape::{Shape, Exclusion};
+pub use text_path::TextPath;
+pub use cache::{FitCache, CachedFit};
+pub use interface::Justification;
+pub use lines::{FitMode, WrapStyle, LetterSpacing};
+use lines::LineRules;
+pub use strategy::{FitStrategy, FirstAcceptable, LargestFontSize, SmallestAreaAtFontSize, BestAspectRatio};
+
+
+mod report;
+mod strategy;
+mod lines;
+mod chain;
+mod live_text;
+mod vertical;
+mod overflow;
+mod orientation;
+mod shape;
+mod text_path;
+mod cache;
+mod interface;
+
+/// A layout with the markup, font and alignment of `src`, but no size set
+pub(crate) fn create_base_layout(src: &(impl LayoutSource + ?Sized)) -> Result<Layout, SvgTextBoxError> {
+	let fd = src.font_description();
+    let hyphenated;
+    let markup = match src.language() {
+        Some(tag) if src.hyphenate() => {
+            hyphenated = hyphenate_markup(src.markup(), patterns(tag)?);
+            hyphenated.as_str()
+        },
+        _ => src.markup()
+    };
+    let alignment = src.alignment();
+    let fontmap = match src.font_registry() {
+        Some(registry) => registry.font_map()?,
+        None => FontMap::get_default()
+            .ok_or(SvgTextBoxError::UnexpectedNone)?
+    };
+    let context = fontmap.create_context()
+    	.ok_or(SvgTextBoxError::UnexpectedNone)?;
+    let direction = src.direction();
+    context.set_base_dir(if direction.is_rtl(markup) { Direction::Rtl } else { Direction::Ltr });
+    if let Some(tag) = src.language() {
+        context.set_language(&Language::from_string(tag));
+    }
+    if src.writing_mode() == WritingMode::Vertical {
+        // the lines are laid out on their side, with glyphs of vertical scripts turned back upright
+        context.set_base_gravity(Gravity::East);
+        context.set_gravity_hint(GravityHint::Natural);
+    }
+    let layout = Layout::new(&context);
+    layout.set_font_description(Some(fd));
+    layout.set_ellipsize(EllipsizeMode::End);
+    layout.set_wrap(WrapMode::Word);
+    layout.set_alignment(alignment);
+    // a chosen direction applies to every paragraph, whichever way its text runs
+    layout.set_auto_dir(direction == TextDirection::Auto);
+    layout.set_justify(src.justification() != Justification::None);
+    layout.set_markup(markup);
+    apply_spacing(&layout, src)?;
+    Ok(layout)
+}
+
+#[cfg(feature = "v1_44")]
+extern "C" {
+	// from pango 1.44. pango-sys 0.9 binds only up to 1.42, and since it links pango,
+	// no later pango-sys can be used alongside it; `layout_line_spacing` checks these
+	fn pango_layout_set_line_spacing(layout: *mut pango_sys::PangoLayout, factor: f32);
+	fn pango_layout_get_line_spacing(layout: *mut pango_sys::PangoLayout) -> f32;
+}
+
+/// Set the distance between baselines of `layout` as a multiple of the natural line height;
+/// zero gives the natural height. Without pango 1.44 there is only the natural height.
+#[cfg(feature = "v1_44")]
+fn set_line_spacing(layout: &Layout, factor: f64) {
+	unsafe {
+		pango_layout_set_line_spacing(layout.to_glib_none().0, factor as f32);
 	}
+}
+
+#[cfg(not(feature = "v1_44"))]
+fn set_line_spacing(_layout: &Layout, _factor: f64) {}
 
-	/// A rendered layout, with information about its final width and height
-	pub struct RenderedTextbox {
-	    pub src: String,
-	    pub width: f64,
-	    pub height: f64,
+/// The factor set by `set_line_spacing`
+#[cfg(feature = "v1_44")]
+fn get_line_spacing(layout: &Layout) -> f64 {
+	f64::from(unsafe { pango_layout_get_line_spacing(layout.to_glib_none().0) })
+}
+
+#[cfg(not(feature = "v1_44"))]
+fn get_line_spacing(_layout: &Layout) -> f64 {
+	0.0
+}
+
+/// Apply the line spacing of `src` to `layout`. Letter spacing depends on the font size,
+/// so is set along with it, and paragraph spacing moves lines down as they are placed.
+fn apply_spacing(layout: &Layout, src: &(impl LayoutSource + ?Sized)) -> Result<(), SvgTextBoxError> {
+	let line_height = src.line_height();
+	if (line_height - 1.0).abs() > f64::EPSILON {
+		if !cfg!(feature = "v1_44") {
+			return Err(SvgTextBoxError::PangoTooOld("line-height needs pango 1.44".to_string()));
+		}
+		set_line_spacing(layout, line_height);
 	}
+	Ok(())
+}
 
-	impl RenderedTextbox {
-
-		/// Create a new svg image from `src`
-	    pub fn new(src: &impl LayoutSource) -> Result<RenderedTextbox, SvgTextBoxError> {
-	        let manager = LayoutManager::new(src)?;
-	        let layout = manager.get_best_fit()?;
-	        let width = src.output_width(layout.get_width());
-	        let height = src.output_height(layout.get_height());
-	        let x = src.output_x();
-	        let vertical_offset = if src.centre_output_vertically() {
-	            let (ink_extents, _logical_extents) = layout.get_extents();
-        		let surplus_height = layout.get_height() - ink_extents.height;
-		        let top_padding = surplus_height / 2;
-		        let offset = top_padding - ink_extents.y;
-	            f64::from(offset / SCALE)
-	        } else {
-	                0.0
-	        };
-        	let y = src.output_y() + vertical_offset;
-	        let writable = Vec::new();
-	        let surface = cairo::SvgSurface::for_stream(width, height, writable);
-	        let context = cairo::Context::new(&surface);
-	        context.move_to(x, y);
-	        pangocairo::functions::show_layout(&context, &layout);
-	        let image_bytes = surface
-	            .finish_output_stream()?
-	            .downcast::<Vec<u8>>()
-	            .map(|v| v.to_vec())?;
-	        let svg = std::str::from_utf8(&image_bytes)?;
-	        let image = RenderedTextbox {
-	        	src: svg.to_string(),
-	        	width,
-	        	height
-	        };
-	        Ok(image)
-	    }
-
-	    /// Insert a background rectangle into the created svg image.
-	    pub fn insert_background_rect(&mut self, attrs: &HashMap<String, String>) -> Result<&mut Self, SvgTextBoxError> {
-	        let mut a = attrs.iter()
-	        	.map(|(k, v)| (k.as_str(), v.to_string()))
-	        	.collect::<BTreeMap<&str, String>>();
-	        a.insert("x", "0".to_string());
-	        a.insert("y", "0".to_string());
-	        a.insert("width", self.width.to_string());
-	        a.insert("height", self.height.to_string());
-	        let b = a.iter()
-	        	.map(|(k, v)| format!("{}=\"{}\"", k, v))
-				.collect::<Vec<String>>()
-				.join(" ");
-			let padding_rect = format!("</defs>\n<g>\n<rect {}/>\n</g>", b);
-						
-	        self.src = self.src
-	        	.replace("</defs>", &padding_rect);
-	        Ok(self)
-	    }
+/// Whether `c` is a break which starts a new paragraph
+pub(crate) fn is_paragraph_break(c: char) -> bool {
+	c == '\n' || c == '\r' || c == '\u{2029}'
+}
+
+/// Whether `line` ends with a break which starts a new paragraph
+pub(crate) fn ends_paragraph(line: &str) -> bool {
+	line.ends_with(is_paragraph_break)
+}
+
+/// The space left after each paragraph of `layout` at its current font size, when it is
+/// `spacing` lines high. In `points * pango::SCALE`.
+pub(crate) fn paragraph_gap(layout: &Layout, spacing: f64) -> i32 {
+	if spacing <= 0.0 {
+		return 0;
 	}
+	let description = layout.get_font_description().unwrap_or_default();
+	let metrics = match layout.get_context().and_then(|c| c.get_metrics(Some(&description), None)) {
+		Some(m) => m,
+		None => return 0
+	};
+	let line_spacing = get_line_spacing(layout);
+	let line_spacing = if line_spacing > 0.0 { line_spacing } else { 1.0 };
+	let line_height = f64::from(metrics.get_ascent() + metrics.get_descent()) * line_spacing;
+	(line_height * spacing).round() as i32
+}
 
-	impl AsRef<str> for RenderedTextbox {
-	    fn as_ref(&self) -> &str {
-	        self.src.as_str()
-	    }
+/// How far each line of `layout` is moved down by a space of `gap` after every paragraph
+/// but the last, in `points * pango::SCALE`. Empty if nothing moves.
+pub(crate) fn paragraph_offsets(layout: &Layout, gap: i32) -> Vec<i32> {
+	let text = match layout.get_text() {
+		Some(t) => t.to_string(),
+		None => return Vec::new()
+	};
+	// breaks at the very end of the text start no paragraph, so leave no space
+	let last_paragraph = text.trim_end_matches(is_paragraph_break).len();
+	let mut iter = match layout.get_iter() {
+		Some(i) if gap > 0 => i,
+		_ => return Vec::new()
+	};
+	let mut offsets = vec![0];
+	let mut offset = 0;
+	while iter.next_line() {
+		let start = iter.get_index() as usize;
+		if start <= last_paragraph && ends_paragraph(&text[..start]) {
+			offset += gap;
+		}
+		offsets.push(offset);
 	}
+	offsets
+}
 
-	impl std::fmt::Display for RenderedTextbox {
-	    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-	        write!(f, "{}", self.src)
-	    }
+/// The largest font size (in `points * pango::SCALE`) which is a possible font size
+/// for every one of `sources` and which fits in all of them.
+/// If a source fits at none of them, the error carries the report of its candidates.
+pub fn shared_font_size<S: LayoutSource + ?Sized>(sources: &[&S]) -> Result<i32, SvgTextBoxError> {
+	let mut shared: Option<BTreeSet<i32>> = None;
+	for src in sources {
+		let sizes = src.possible_font_sizes()
+			.collect::<BTreeSet<i32>>();
+		shared = match shared {
+			Some(s) => Some(s.intersection(&sizes).cloned().collect()),
+			None => Some(sizes)
+		};
 	}
+	let mut font_sizes = shared
+		.unwrap_or_default()
+		.into_iter()
+		.collect::<Vec<i32>>();
+	for src in sources {
+		if font_sizes.is_empty() {
+			return Err(SvgTextBoxError::NoValidFontSizes);
+		}
+		let mut manager = LayoutManager::new(*src)?;
+		manager.font_sizes = font_sizes.clone();
+		let largest = manager.largest_font_size()?;
+		// anything larger than this will not fit this source, so need not be tried on the rest
+		font_sizes.retain(|size| *size <= largest);
+	}
+	font_sizes.last()
+		.cloned()
+		.ok_or(SvgTextBoxError::NoValidFontSizes)
 }
 
 pub(crate) struct LayoutManager {
 	dimensions: Vec<(i32, i32)>,
 	font_sizes: Vec<i32>,
+	fit_mode: FitMode,
+	avoid_stranded_lines: bool,
+	letter_spacing: LetterSpacing,
+	paragraph_spacing: f64,
+	shape: Shape,
+	exclusions: Vec<Exclusion>,
+	wrap_style: WrapStyle,
+	justification: Justification,
+	vertical_alignment: VerticalAlignment,
+	overflow: OverflowPolicy,
 	base_layout: Layout
 }
 
 impl LayoutManager {
 	
-	pub(crate) fn new(src: &impl LayoutSource) -> Result<LayoutManager, SvgTextBoxError> {
-		let fd = src.font_description();
+	pub(crate) fn new(src: &(impl LayoutSource + ?Sized)) -> Result<LayoutManager, SvgTextBoxError> {
         let possible_font_sizes = src.possible_font_sizes();
-        let markup = src.markup();
-        let alignment = src.alignment();
-        let fontmap = FontMap::get_default()
-        	.ok_or(SvgTextBoxError::UnexpectedNone)?;
-        let context = fontmap.create_context()
-        	.ok_or(SvgTextBoxError::UnexpectedNone)?;
-        let layout = Layout::new(&context);
-        layout.set_font_description(Some(fd));
-        layout.set_ellipsize(EllipsizeMode::End);
-        layout.set_wrap(WrapMode::Word);
-        layout.set_alignment(alignment);
-        layout.set_markup(markup);
+        let layout = create_base_layout(src)?;
         let possible_font_sizes = possible_font_sizes
         	.collect::<BTreeSet<i32>>() // want to be sure these are sorted
         	.into_iter()
@@ -231,31 +309,162 @@ impl LayoutManager {
         if possible_widths.peek().is_none() {
         	return Err(SvgTextBoxError::NoValidWidths);
         }
-        let possible_dimensions = src.possible_widths()
-            .flat_map(move |v|
-                iter::repeat(v)
-                    .zip(src.possible_heights()))
-            .collect::<Vec<(i32, i32)>>();
+        let on_path = src.text_path().is_some();
+        let orientation = Orientation::new(src.rotation(), src.writing_mode());
+        let possible_dimensions = match src.text_path() {
+            // text on a path is fitted to the length of the path, whatever the width of the box
+            Some(path) => {
+                let length = (path.length() * f64::from(pango::SCALE)) as i32;
+                src.possible_heights()
+                    .map(|h| (length, h))
+                    .collect::<Vec<(i32, i32)>>()
+            },
+            None => src.possible_widths()
+                .flat_map(move |v|
+                    iter::repeat(v)
+                        .zip(src.possible_heights()))
+                .map(|(w, h)| orientation.layout_size(w, h))
+                .collect::<Vec<(i32, i32)>>()
+        };
 
 		Ok(LayoutManager {
 			dimensions: possible_dimensions,
 			font_sizes: possible_font_sizes,
+			fit_mode: if on_path { FitMode::SingleLine } else { src.fit_mode() },
+			avoid_stranded_lines: src.avoid_stranded_lines(),
+			letter_spacing: src.letter_spacing(),
+			paragraph_spacing: src.paragraph_spacing(),
+			shape: src.shape().filter(|_| !on_path).cloned().unwrap_or_default(),
+			exclusions: if on_path { Vec::new() } else { src.exclusions().to_vec() },
+			wrap_style: src.wrap_style(),
+			justification: src.justification(),
+			vertical_alignment: src.vertical_alignment(),
+			overflow: src.overflow(),
 			base_layout: layout
 		})
 	}
 
-	pub(crate) fn get_best_fit(self) -> Result<Layout, SvgTextBoxError> {
-		for (width, height) in self.dimensions {
+	/// The shape and exclusions the text is set between, unless it simply fills the box
+	fn region(&self) -> Option<Region> {
+		if self.shape == Shape::Rectangle && self.exclusions.is_empty() {
+			return None;
+		}
+		Some(Region {
+			shape: &self.shape,
+			exclusions: &self.exclusions
+		})
+	}
+
+	fn rules(&self) -> LineRules {
+		LineRules {
+			mode: self.fit_mode,
+			avoid_stranded_lines: self.avoid_stranded_lines,
+			region: self.region(),
+			letter_spacing: self.letter_spacing,
+			paragraph_spacing: self.paragraph_spacing
+		}
+	}
+
+	/// The largest font size which fits any of the possible dimensions.
+	/// If none fits, the report of every candidate tried is returned with the error.
+	pub(crate) fn largest_font_size(&self) -> Result<i32, SvgTextBoxError> {
+		let mut report = FitReport::default();
+		for (width, height) in self.dimensions.iter() {
+			self.base_layout.set_width(*width);
+			self.base_layout.set_height(*height);
+			match self.base_layout.grow_to_maximum_font_size(&self.font_sizes, self.rules()) {
+				Ok(attempt) => report.attempts.push(attempt),
+				Err(SvgTextBoxError::FitFailed(failed)) => report.attempts.extend(failed.attempts),
+				Err(e) => return Err(e),
+			}
+		}
+		report.chosen = LargestFontSize.choose(&report.attempts);
+		match report.font_size() {
+			Some(size) => Ok(size),
+			None => Err(SvgTextBoxError::FitFailed(report))
+		}
+	}
+
+	/// Find the dimensions `strategy` prefers, along with a report
+	/// of every candidate tried. If nothing fits, the report is returned with the error.
+	pub(crate) fn get_best_fit(self, strategy: &dyn FitStrategy) -> Result<(Layout, FitReport), SvgTextBoxError> {
+		let mut report = FitReport::default();
+		let rules = self.rules();
+		for (width, height) in self.dimensions.iter().cloned() {
 			self.base_layout.set_width(width);
 			self.base_layout.set_height(height);
-			let result = self.base_layout.grow_to_maximum_font_size(&self.font_sizes);
-			match result {
-				Ok(_) => return Ok(self.base_layout),
-				Err(SvgTextBoxError::CouldNotFit) => {},
-				Err(e) => return Err(e),
+			let attempt = self.base_layout.try_font_sizes(&self.font_sizes, rules)?;
+			let accepted = strategy.accept_immediately(&attempt);
+			report.attempts.push(attempt);
+			if accepted {
+				break;
+			}
+		}
+		report.chosen = strategy.choose(&report.attempts);
+		let chosen = report.chosen()
+			.and_then(|a| a.largest_fit.map(|f| (a.width, a.height, f)));
+		let (width, height, font_size) = match (chosen, self.overflow) {
+			(Some(c), _) => c,
+			(None, OverflowPolicy::Error) => return Err(SvgTextBoxError::FitFailed(report)),
+			(None, policy) => {
+				// truncate the text in the most preferred dimensions, at the smallest size
+				let font_size = self.font_sizes[0];
+				let first = report.attempts.first()
+					.map(|a| (a.width, a.height))
+					.ok_or(SvgTextBoxError::UnexpectedNone)?;
+				report.chosen = Some(0);
+				report.truncation = Some(Truncation {
+					policy,
+					font_size
+				});
+				(first.0, first.1, font_size)
 			}
+		};
+		Ok(self.finish(width, height, font_size, report))
+	}
+
+	/// Lay the text out again as it was when `fit` was made, without searching
+	pub(crate) fn apply_cached_fit(self, fit: &CachedFit) -> (Layout, FitReport) {
+		let report = FitReport {
+			attempts: vec![DimensionAttempt {
+				width: fit.width,
+				height: fit.height,
+				largest_fit: if fit.truncation.is_none() { Some(fit.font_size) } else { None },
+				smallest_failure: None
+			}],
+			chosen: Some(0),
+			truncation: fit.truncation.map(|policy| Truncation {
+				policy,
+				font_size: fit.font_size
+			}),
+			cached: true,
+			flowed: false
+		};
+		self.finish(fit.width, fit.height, fit.font_size, report)
+	}
+
+	/// Set the layout to the chosen dimensions and font size, then break its lines
+	fn finish(self, width: i32, height: i32, font_size: i32, mut report: FitReport) -> (Layout, FitReport) {
+		if let Some(t) = &report.truncation {
+			self.base_layout.set_ellipsize(t.policy.ellipsize_mode());
+		}
+		self.base_layout.set_width(width);
+		self.base_layout.set_height(height);
+		self.base_layout.resize(font_size, self.letter_spacing);
+		if report.truncation.is_some() {
+			return (self.base_layout, report);
+		}
+		if let Some(region) = self.region() {
+			report.flowed = set_in_shape(&self.base_layout, region, self.vertical_alignment, self.paragraph_spacing);
+			return (self.base_layout, report);
 		}
-		Err(SvgTextBoxError::CouldNotFit)
+		if self.wrap_style == WrapStyle::Balanced {
+			self.base_layout.balance_lines();
+		}
+		if self.justification == Justification::JustifyAllLines {
+			self.base_layout.justify_last_lines();
+		}
+		(self.base_layout, report)
 	}
 }
 
@@ -264,18 +473,57 @@ trait LayoutExtension {
     fn font_size(&self) -> i32;
     // set a new base font size for this layout
     fn set_font_size(&self, new_font_size: i32);
+    /// Replace the letter spacing of the whole text, leaving any set in the markup
+    fn set_letter_spacing(&self, spacing: i32);
+    /// Set a new base font size, along with the letter spacing at that size
+    fn resize(&self, new_font_size: i32, letter_spacing: LetterSpacing);
     /// Whether this layout currently fits
     fn fits(&self) -> bool;
+    /// Why this layout currently does not fit, or does not have the lines `mode` allows
+    fn fit_failure(&self, mode: FitMode) -> Option<FitFailureReason>;
+    /// Why this layout is not a single line within its width, whatever its height
+    fn single_line_failure(&self) -> Option<FitFailureReason>;
+    /// How far the text would extend past (width, height) if it were not constrained,
+    /// with `paragraph_gap` left after each paragraph
+    fn overflow(&self, paragraph_gap: i32) -> (i32, i32);
+    /// The byte index of the start of the first line which extends past the layout height
+    /// once `paragraph_gap` is left after each paragraph, if there is one.
+    /// Only meaningful when the layout is not ellipsized.
+    fn hidden_text_index(&self, paragraph_gap: i32) -> Option<i32>;
+    /// Whether any paragraph of more than one line starts or ends with a line holding a
+    /// single word. Paragraphs are split by forced line breaks as well as newlines.
+    fn stranded_line(&self) -> Option<FitFailureReason>;
+    /// Why this layout currently does not fit, or does not have the lines `rules` allow.
+    /// If the rules give a shape or exclusions, the text must be able to flow between them.
+    fn rule_failure(&self, rules: LineRules) -> Option<FitFailureReason>;
     /// Change this layout's font size to `n`. If it fits, return Ordering::Less.
-    /// If it does not, return Ordering::Greater.
-    fn change_size_and_check_fits(&self, n: i32) -> Ordering;
-    /// Grow this layout to the maximum font size that will fit
-    fn grow_to_maximum_font_size(&self, possible_font_sizes: &[i32]) -> Result<(), SvgTextBoxError>;
+    /// If it does not, return Ordering::Greater. Too few lines for `rules` means
+    /// a larger size is needed, so also gives Ordering::Less.
+    fn change_size_and_check_fits(&self, n: i32, rules: LineRules) -> Ordering;
+    /// Narrow this layout as far as possible without adding a line or no longer fitting.
+    /// The layout width is reduced, so the text must be moved to keep its alignment in the box.
+    fn balance_lines(&self);
+    /// Stretch the spaces in the last line of each paragraph so that it fills the width,
+    /// as pango only justifies the other lines. Nothing changes if the result would not fit.
+    fn justify_last_lines(&self);
+    /// Grow this layout to the maximum font size that will fit, returning the attempt.
+    /// If no size fits, the attempt is reported with the error.
+    fn grow_to_maximum_font_size(&self, possible_font_sizes: &[i32], rules: LineRules) -> Result<DimensionAttempt, SvgTextBoxError>;
+    /// Grow this layout to the maximum font size that will fit, recording
+    /// the largest size that fit and the smallest that did not.
+    fn try_font_sizes(&self, possible_font_sizes: &[i32], rules: LineRules) -> Result<DimensionAttempt, SvgTextBoxError>;
 }
 
 impl LayoutExtension for Layout {
     
     fn fits(&self) -> bool {
+        self.fit_failure(FitMode::FillBox).is_none()
+    }
+
+    fn fit_failure(&self, mode: FitMode) -> Option<FitFailureReason> {
+        if mode == FitMode::SingleLine {
+            return self.single_line_failure();
+        }
         // Pango has a mystery habit of dropping lines
         // off the end if you let it.
         // so we check what the index of the char closest
@@ -291,37 +539,267 @@ impl LayoutExtension for Layout {
         // We have to get this from the text itself:
         let text_string = self.get_text().expect("No text");
         let dropped_chars = last_char_index != (text_string.len() as i32 - 1);
-        !(self.is_ellipsized() || dropped_chars)
+        if self.is_ellipsized() {
+            Some(FitFailureReason::Ellipsized)
+        } else if dropped_chars {
+            Some(FitFailureReason::DroppedChars)
+        } else {
+            mode.check(self.get_line_count())
+        }
     }
 
-    fn change_size_and_check_fits(&self, size: i32) -> Ordering {
-        self.set_font_size(size);
-        if self.fits() {
-            Ordering::Less
+    fn single_line_failure(&self) -> Option<FitFailureReason> {
+        // a single line may be as high as it needs, so the height is lifted while it is set
+        let height = self.get_height();
+        self.set_height(i32::MAX);
+        let (_ink_extents, logical_extents) = self.get_extents();
+        let failure = if self.get_line_count() > 1 {
+            Some(FitFailureReason::TooManyLines)
+        } else if logical_extents.x + logical_extents.width > self.get_width() {
+            Some(FitFailureReason::TooWide)
         } else {
-            Ordering::Greater
+            None
+        };
+        self.set_height(height);
+        failure
+    }
+
+    fn overflow(&self, paragraph_gap: i32) -> (i32, i32) {
+        // with no ellipsization, pango ignores the height and lays out everything
+        let unconstrained = match self.copy() {
+            Some(l) => l,
+            None => return (0, 0)
+        };
+        unconstrained.set_ellipsize(EllipsizeMode::None);
+        let (_ink_extents, logical_extents) = unconstrained.get_extents();
+        let paragraph_space = paragraph_offsets(&unconstrained, paragraph_gap).last()
+            .cloned()
+            .unwrap_or(0);
+        let overflow_width = logical_extents.x + logical_extents.width - self.get_width();
+        let overflow_height = logical_extents.y + logical_extents.height + paragraph_space - self.get_height();
+        (overflow_width.max(0), overflow_height.max(0))
+    }
+
+    fn hidden_text_index(&self, paragraph_gap: i32) -> Option<i32> {
+        // the space between paragraphs moves lines down, so any line may be the first hidden
+        let offsets = paragraph_offsets(self, paragraph_gap);
+        let mut iter = self.get_iter()?;
+        let mut line = 0;
+        loop {
+            let (_top, bottom) = iter.get_line_yrange();
+            if bottom + offsets.get(line).cloned().unwrap_or(0) > self.get_height() {
+                return Some(iter.get_index());
+            }
+            if !iter.next_line() {
+                return None;
+            }
+            line += 1;
         }
     }
 
-    fn grow_to_maximum_font_size(&self, v: &[i32]) -> Result<(), SvgTextBoxError> {
+    fn justify_last_lines(&self) {
+        let text = match self.get_text() {
+            Some(t) => t.to_string(),
+            None => return
+        };
+        let original = self.get_attributes();
+        let stretched = original.as_ref()
+            .and_then(|a| a.copy())
+            .unwrap_or_else(AttrList::new);
+        let line_count = self.get_line_count();
+        let mut iter = match self.get_iter() {
+            Some(i) => i,
+            None => return
+        };
+        loop {
+            let start = iter.get_index() as usize;
+            let (_ink, logical) = iter.get_line_extents();
+            let last_line = !iter.next_line();
+            let end = if last_line { text.len() } else { iter.get_index() as usize };
+            let line = &text[start..end];
+            let paragraph_end = last_line || ends_paragraph(line) || line.ends_with('\u{2028}');
+            let content = line.trim_end();
+            let spaces = content.match_indices(' ')
+                .map(|(i, _)| start + i)
+                .collect::<Vec<usize>>();
+            let extra = self.get_width() - logical.width;
+            if paragraph_end && !spaces.is_empty() && extra > 0 {
+                // rounded down, so the line never becomes wider than the layout
+                let spacing = extra / spaces.len() as i32;
+                for i in spaces {
+                    if let Some(mut a) = Attribute::new_letter_spacing(spacing) {
+                        a.set_start_index(i as u32);
+                        a.set_end_index(i as u32 + 1);
+                        stretched.insert(a);
+                    }
+                }
+            }
+            if last_line {
+                break;
+            }
+        }
+        self.set_attributes(Some(&stretched));
+        if !self.fits() || self.get_line_count() != line_count {
+            self.set_attributes(original.as_ref());
+        }
+    }
+
+    fn balance_lines(&self) {
+        let line_count = self.get_line_count();
+        if line_count < 2 {
+            return;
+        }
+        // narrowest width known to work, and widest known not to
+        let mut works = self.get_width();
+        let mut fails = 0;
+        while works - fails > 1 {
+            let middle = fails + (works - fails) / 2;
+            self.set_width(middle);
+            if self.get_line_count() == line_count && self.fits() {
+                works = middle;
+            } else {
+                fails = middle;
+            }
+        }
+        self.set_width(works);
+    }
+
+    fn stranded_line(&self) -> Option<FitFailureReason> {
+        let text = self.get_text()?.to_string();
+        let mut iter = self.get_iter()?;
+        let mut lines = Vec::new();
+        loop {
+            let start = iter.get_index() as usize;
+            let more = iter.next_line();
+            let end = if more { iter.get_index() as usize } else { text.len() };
+            lines.push(&text[start..end]);
+            if !more {
+                break;
+            }
+        }
+        let words = |line: &str| line.split_whitespace().count();
+        let mut paragraph = Vec::new();
+        for (i, line) in lines.iter().enumerate() {
+            paragraph.push(*line);
+            let forced_break = ends_paragraph(line) || line.ends_with('\u{2028}');
+            if !forced_break && i + 1 < lines.len() {
+                continue;
+            }
+            if paragraph.len() > 1 {
+                if paragraph.last().map(|l| words(l) == 1).unwrap_or(false) {
+                    return Some(FitFailureReason::Widow);
+                }
+                if words(paragraph[0]) == 1 {
+                    return Some(FitFailureReason::Orphan);
+                }
+            }
+            paragraph.clear();
+        }
+        None
+    }
+
+    fn rule_failure(&self, rules: LineRules) -> Option<FitFailureReason> {
+        match rules.region {
+            Some(region) => match flow(self, region, 0, rules.paragraph_spacing) {
+                Some(f) => rules.mode.check(f.lines.len() as i32),
+                None => Some(FitFailureReason::OutsideShape)
+            },
+            None => self.fit_failure(rules.mode).or_else(|| {
+                // pango sets the lines without the space between paragraphs, which must fit too
+                let gap = paragraph_gap(self, rules.paragraph_spacing);
+                let paragraph_space = paragraph_offsets(self, gap).last()
+                    .cloned()
+                    .unwrap_or(0);
+                let (_ink_extents, logical_extents) = self.get_extents();
+                if paragraph_space > 0 && logical_extents.y + logical_extents.height + paragraph_space > self.get_height() {
+                    Some(FitFailureReason::TooHigh)
+                } else {
+                    None
+                }
+            })
+        }
+    }
+
+    fn change_size_and_check_fits(&self, size: i32, rules: LineRules) -> Ordering {
+        self.resize(size, rules.letter_spacing);
+        match self.rule_failure(rules) {
+            None | Some(FitFailureReason::TooFewLines) => Ordering::Less,
+            Some(_) => Ordering::Greater
+        }
+    }
+
+    fn grow_to_maximum_font_size(&self, v: &[i32], rules: LineRules) -> Result<DimensionAttempt, SvgTextBoxError> {
+        let attempt = self.try_font_sizes(v, rules)?;
+        if attempt.fits() {
+            Ok(attempt)
+        } else {
+            Err(SvgTextBoxError::FitFailed(FitReport {
+                attempts: vec![attempt],
+                ..FitReport::default()
+            }))
+        }
+    }
+
+    fn try_font_sizes(&self, v: &[i32], rules: LineRules) -> Result<DimensionAttempt, SvgTextBoxError> {
+        let failure = |reason| {
+            let (overflow_width, overflow_height) = self.overflow(paragraph_gap(self, rules.paragraph_spacing));
+            FitFailure {
+                font_size: self.font_size(),
+                reason,
+                overflow_width,
+                overflow_height
+            }
+        };
         // this search will always return an error representing
         // the index of where in `possible_font_sizes` a notional
         // successful result would have been found -- i.e the point
         // at which preceding font sizes would fit and at which succeeding
         // font sizes would not
-        let search_result = v.binary_search_by(|n| self.change_size_and_check_fits(*n));
+        let search_result = v.binary_search_by(|n| self.change_size_and_check_fits(*n, rules));
         let index = search_result.err()
         	.ok_or(SvgTextBoxError::UnexpectedNone)?;
-        // if this is at zero, no possible size would fit
-        if index == 0 {
-            return Err(SvgTextBoxError::CouldNotFit);
+        // the first value which does not fit is at the index itself
+        let mut smallest_failure = match v.get(index) {
+            Some(size) => {
+                self.resize(*size, rules.letter_spacing);
+                let reason = self.rule_failure(rules)
+                    .ok_or(SvgTextBoxError::UnexpectedNone)?;
+                Some(failure(reason))
+            },
+            None => None
+        };
+        // if the index is at zero, no possible size would fit;
+        // in any other case, the last value which does fit must be that immediately preceding,
+        // unless it has too few lines or strands a line
+        let mut candidate = index.checked_sub(1);
+        while let Some(i) = candidate {
+            let size = v.get(i)
+                .ok_or(SvgTextBoxError::UnexpectedNone)?;
+            self.resize(*size, rules.letter_spacing);
+            let reason = match self.rule_failure(rules) {
+                None if rules.avoid_stranded_lines && rules.region.is_none() => self.stranded_line(),
+                r => r
+            };
+            candidate = match reason {
+                None => break,
+                // sizes with too few lines were passed over in the search,
+                // so all the smaller sizes have too few lines as well
+                Some(FitFailureReason::TooFewLines) => None,
+                // but stranded lines come and go, so step down until there are none
+                Some(_) => i.checked_sub(1)
+            };
+            smallest_failure = reason.map(failure);
         }
-        // in any other case, the last value which does fit must be that immediately preceding:
-        let last_fit = index - 1;
-        let r = v.get(last_fit)
-        	.ok_or(SvgTextBoxError::UnexpectedNone)?;
-        self.set_font_size(*r);
-        Ok(())
+        let largest_fit = match candidate {
+            Some(i) => Some(*v.get(i).ok_or(SvgTextBoxError::UnexpectedNone)?),
+            None => None
+        };
+        Ok(DimensionAttempt {
+            width: self.get_width(),
+            height: self.get_height(),
+            largest_fit,
+            smallest_failure
+        })
     }
 
     fn font_size(&self) -> i32 {
@@ -333,6 +811,32 @@ impl LayoutExtension for Layout {
         fd.set_size(new_font_size);
         self.set_font_description(Some(&fd));
     }
+
+    fn set_letter_spacing(&self, spacing: i32) {
+        let class = match Attribute::new_letter_spacing(0) {
+            Some(a) => a.get_attr_class(),
+            None => return
+        };
+        let attrs = self.get_attributes()
+            .and_then(|a| a.copy())
+            .unwrap_or_else(AttrList::new);
+        // only spacing set here covers the whole text
+        attrs.filter(|a| a.get_attr_class() == class && a.get_start_index() == 0 && a.get_end_index() == u32::MAX);
+        if spacing != 0 {
+            if let Some(mut a) = Attribute::new_letter_spacing(spacing) {
+                a.set_start_index(0);
+                a.set_end_index(u32::MAX);
+                // anything set in the markup should take precedence
+                attrs.insert_before(a);
+            }
+        }
+        self.set_attributes(Some(&attrs));
+    }
+
+    fn resize(&self, new_font_size: i32, letter_spacing: LetterSpacing) {
+        self.set_font_size(new_font_size);
+        self.set_letter_spacing(letter_spacing.at(new_font_size));
+    }
 }
 
 #[cfg(test)]
@@ -340,39 +844,160 @@ mod tests {
 	use super::*;
 	use pango::SCALE;
 
-	#[test]
-	fn new_rendered_textbox() {
-		unimplemented!();
+	pub(super) struct TestSource {
+		widths: Vec<i32>,
+		heights: Vec<i32>,
+		font_description: pango::FontDescription,
+		markup: &'static str,
+		text_mode: SvgTextMode,
+		justification: Justification,
 	}
 
-	#[test]
-	fn insert_background_rect() {
-		unimplemented!();
+	impl TestSource {
+		pub(super) fn new(width: i32, height: i32) -> Self {
+			TestSource {
+				widths: vec![width * SCALE],
+				heights: vec![height * SCALE],
+				font_description: pango::FontDescription::new(),
+				markup: "Hello World",
+				text_mode: SvgTextMode::Outlines,
+				justification: Justification::None,
+			}
+		}
+
+		pub(super) fn with_markup(mut self, markup: &'static str) -> Self {
+			self.markup = markup;
+			self
+		}
+
+		pub(super) fn with_text_mode(mut self, text_mode: SvgTextMode) -> Self {
+			self.text_mode = text_mode;
+			self
+		}
+
+		pub(super) fn with_justification(mut self, justification: Justification) -> Self {
+			self.justification = justification;
+			self
+		}
 	}
 
-	#[test]
-	fn rendered_textbox_display() {
-		let tb = RenderedTextbox{
-			src: "Test".to_string(),
-			width: 100.0,
-			height: 100.0
-		};
+	impl LayoutSource for TestSource {
+		fn possible_font_sizes<'a>(&'a self) -> Box<dyn Iterator<Item=i32> + 'a> {
+			Box::new((1..100).map(|i| i * SCALE))
+		}
+		fn possible_widths<'a>(&'a self) -> Box<dyn Iterator<Item=i32> + 'a> {
+			Box::new(self.widths.iter().cloned())
+		}
+		fn possible_heights<'a>(&'a self) -> Box<dyn Iterator<Item=i32> + 'a> {
+			Box::new(self.heights.iter().cloned())
+		}
+		fn font_description(&self) -> &pango::FontDescription {
+			&self.font_description
+		}
+		fn markup(&self) -> &str {
+			self.markup
+		}
+		fn alignment(&self) -> pango::Alignment {
+			pango::Alignment::Center
+		}
+		fn text_mode(&self) -> SvgTextMode {
+			self.text_mode
+		}
+		fn justification(&self) -> Justification {
+			self.justification
+		}
+	}
 
-		assert_eq!(tb.to_string(), tb.src);
-		assert_eq!(tb.as_ref(), tb.src);
+	#[test]
+	fn layout_shared_font_size() {
+		let narrow = TestSource::new(50, 50);
+		let wide = TestSource::new(300, 150);
+		let narrow_alone = shared_font_size(&[&narrow]).unwrap();
+		let wide_alone = shared_font_size(&[&wide]).unwrap();
+		assert!(narrow_alone < wide_alone);
+		assert_eq!(shared_font_size(&[&narrow, &wide]).unwrap(), narrow_alone);
+		assert_eq!(shared_font_size(&[&wide, &narrow]).unwrap(), narrow_alone);
+		// a source which fits at no shared size explains why
+		let tiny = TestSource::new(1, 1);
+		match shared_font_size(&[&wide, &tiny]) {
+			Err(SvgTextBoxError::FitFailed(report)) => {
+				assert_eq!(report.attempts.len(), 1);
+				assert!(!report.attempts[0].fits());
+			},
+			_ => panic!("expected the fit report of the source which did not fit")
+		}
 	}
 
 	#[test]
 	fn new_layout_manager() {
-		unimplemented!();
+		let src = TestSource {
+			widths: vec![100 * SCALE, 200 * SCALE],
+			..TestSource::new(100, 50)
+		};
+		let manager = LayoutManager::new(&src).unwrap();
+		assert_eq!(manager.dimensions, vec![(100 * SCALE, 50 * SCALE), (200 * SCALE, 50 * SCALE)]);
+		assert_eq!(manager.font_sizes, (1..100).map(|i| i * SCALE).collect::<Vec<i32>>());
+		assert_eq!(manager.fit_mode, FitMode::FillBox);
+		assert!(manager.region().is_none());
+
+		let no_widths = TestSource {
+			widths: Vec::new(),
+			..TestSource::new(100, 50)
+		};
+		match LayoutManager::new(&no_widths) {
+			Err(SvgTextBoxError::NoValidWidths) => (),
+			_ => panic!("expected no valid widths")
+		}
+		let no_heights = TestSource {
+			heights: Vec::new(),
+			..TestSource::new(100, 50)
+		};
+		match LayoutManager::new(&no_heights) {
+			Err(SvgTextBoxError::NoValidHeights) => (),
+			_ => panic!("expected no valid heights")
+		}
 	}
 
 	#[test]
 	fn get_best_fit() {
-		unimplemented!();
+		// nothing fits in a box a point high
+		let src = TestSource {
+			heights: vec![SCALE, 50 * SCALE, 100 * SCALE],
+			..TestSource::new(100, 50)
+		};
+		let (layout, report) = LayoutManager::new(&src).unwrap()
+			.get_best_fit(&FirstAcceptable)
+			.unwrap();
+		assert_eq!(report.attempts.len(), 2);
+		assert!(!report.attempts[0].fits());
+		assert_eq!(report.chosen, Some(1));
+		assert_eq!(layout.get_height(), 50 * SCALE);
+		assert_eq!(Some(layout.font_size()), report.font_size());
+
+		let (layout, report) = LayoutManager::new(&src).unwrap()
+			.get_best_fit(&LargestFontSize)
+			.unwrap();
+		assert_eq!(report.attempts.len(), 3);
+		let largest = report.attempts.iter()
+			.filter_map(|a| a.largest_fit)
+			.max();
+		assert_eq!(report.font_size(), largest);
+		assert_eq!(Some(layout.font_size()), largest);
+
+		let too_low = TestSource {
+			heights: vec![SCALE],
+			..TestSource::new(100, 50)
+		};
+		match LayoutManager::new(&too_low).unwrap().get_best_fit(&FirstAcceptable) {
+			Err(SvgTextBoxError::FitFailed(report)) => {
+				assert_eq!(report.attempts.len(), 1);
+				assert!(report.chosen.is_none());
+			},
+			_ => panic!("expected the fit to fail with its report")
+		}
 	}
 
-    fn create_layout_for_testing() -> Layout {
+    pub(super) fn create_layout_for_testing() -> Layout {
         let fontmap = pangocairo::FontMap::get_default().expect("Could not get pango fontmap");
         let context = fontmap
             .create_context()
@@ -409,18 +1034,188 @@ mod tests {
 	fn layout_grow_to_maximum_font_size() {
 		let l = create_layout_for_testing();
 		let sizes = (50..60).map(|i| i * SCALE).collect::<Vec<i32>>();
-		l.grow_to_maximum_font_size(&sizes).unwrap();
+		l.grow_to_maximum_font_size(&sizes, LineRules::default()).unwrap();
 		assert_eq!(l.font_size(), 57344);
-		l.grow_to_maximum_font_size(&vec![10 * SCALE]).unwrap();
+		l.grow_to_maximum_font_size(&vec![10 * SCALE], LineRules::default()).unwrap();
 		assert_eq!(l.font_size(), 10 * SCALE);
-		let e = l.grow_to_maximum_font_size(&vec![70 * SCALE]);
-		assert!(e.is_err());
-		let x = l.change_size_and_check_fits(70 * SCALE);
+		match l.grow_to_maximum_font_size(&vec![70 * SCALE], LineRules::default()) {
+			Err(SvgTextBoxError::FitFailed(report)) => {
+				assert_eq!(report.attempts.len(), 1);
+				assert!(report.chosen.is_none());
+			},
+			_ => panic!("expected the failed attempt to be reported")
+		}
+		let x = l.change_size_and_check_fits(70 * SCALE, FitMode::FillBox.into());
 		assert_eq!(x, Ordering::Greater);
-		let y = l.change_size_and_check_fits(10 * SCALE);
+		let y = l.change_size_and_check_fits(10 * SCALE, FitMode::FillBox.into());
 		assert_eq!(y, Ordering::Less);
 	}
 
+	#[test]
+	fn layout_try_font_sizes() {
+		let l = create_layout_for_testing();
+		let sizes = (50..60).map(|i| i * SCALE).collect::<Vec<i32>>();
+		let attempt = l.try_font_sizes(&sizes, LineRules::default()).unwrap();
+		assert_eq!(attempt.width, 300 * SCALE);
+		assert_eq!(attempt.height, 150 * SCALE);
+		assert_eq!(attempt.largest_fit, Some(56 * SCALE));
+		let failure = attempt.smallest_failure.unwrap();
+		assert_eq!(failure.font_size, 57 * SCALE);
+		assert!(failure.overflow_width > 0 || failure.overflow_height > 0);
+		assert_eq!(l.font_size(), 56 * SCALE);
+
+		let attempt = l.try_font_sizes(&vec![70 * SCALE], LineRules::default()).unwrap();
+		assert!(!attempt.fits());
+		assert_eq!(attempt.smallest_failure.unwrap().font_size, 70 * SCALE);
+
+		let attempt = l.try_font_sizes(&vec![10 * SCALE], LineRules::default()).unwrap();
+		assert!(attempt.fits());
+		assert!(attempt.smallest_failure.is_none());
+	}
+
+	#[test]
+	#[cfg(feature = "v1_44")]
+	fn layout_line_spacing() {
+		let l = create_layout_for_testing();
+		l.set_markup("One\nTwo");
+		let second_baseline = |l: &Layout| {
+			let mut iter = l.get_iter().unwrap();
+			iter.next_line();
+			iter.get_baseline()
+		};
+		let natural = second_baseline(&l);
+		assert_eq!(get_line_spacing(&l), 0.0);
+		set_line_spacing(&l, 2.0);
+		assert_eq!(get_line_spacing(&l), 2.0);
+		assert!(second_baseline(&l) > natural);
+		set_line_spacing(&l, 0.0);
+		assert_eq!(second_baseline(&l), natural);
+	}
+
+	#[test]
+	fn layout_paragraph_spacing() {
+		let l = create_layout_for_testing();
+		l.set_markup("One\nTwo\n\nThree");
+		l.set_font_size(20 * SCALE);
+		let gap = paragraph_gap(&l, 1.0);
+		assert!(gap > 0);
+		assert_eq!(paragraph_gap(&l, 0.0), 0);
+		// the empty line is a paragraph of its own
+		assert_eq!(paragraph_offsets(&l, gap), vec![0, gap, 2 * gap, 3 * gap]);
+		assert!(paragraph_offsets(&l, 0).is_empty());
+		// the text is left as it was
+		assert_eq!(l.get_text().unwrap().as_str(), "One\nTwo\n\nThree");
+
+		// the lines fit exactly, until the paragraphs are spaced apart
+		let (_ink, logical) = l.get_extents();
+		l.set_height(logical.y + logical.height);
+		let spaced = LineRules {
+			paragraph_spacing: 1.0,
+			..LineRules::default()
+		};
+		assert_eq!(l.rule_failure(LineRules::default()), None);
+		assert_eq!(l.rule_failure(spaced), Some(FitFailureReason::TooHigh));
+		assert_eq!(l.overflow(gap).1, 3 * gap);
+		assert_eq!(l.hidden_text_index(0), None);
+		assert_eq!(l.hidden_text_index(gap), Some("One\nTwo\n".len() as i32));
+
+		// a break at the end starts no paragraph
+		l.set_markup("One\n");
+		assert_eq!(paragraph_offsets(&l, gap), vec![0, 0]);
+	}
+
+	#[test]
+	fn layout_balance_lines() {
+		let l = create_layout_for_testing();
+		l.set_markup("The quick brown fox jumps over the lazy dog");
+		l.set_font_size(30 * SCALE);
+		assert!(l.fits());
+		let lines = l.get_line_count();
+		assert!(lines > 1);
+		l.balance_lines();
+		assert_eq!(l.get_line_count(), lines);
+		assert!(l.fits());
+		assert!(l.get_width() < 300 * SCALE);
+		assert_eq!(l.font_size(), 30 * SCALE);
+		// any narrower would need another line
+		l.set_width(l.get_width() - 1);
+		assert!(l.get_line_count() > lines || !l.fits());
+	}
+
+	#[test]
+	fn layout_justify_last_lines() {
+		let l = create_layout_for_testing();
+		l.set_markup("Hello World\u{2028}Hello World");
+		l.set_font_size(20 * SCALE);
+		assert!(l.fits());
+		let first_line_width = |l: &Layout| l.get_iter().unwrap().get_line_extents().1.width;
+		let ragged = first_line_width(&l);
+		assert!(ragged < l.get_width());
+		// a line separator ends the line as a paragraph does, so the line before it is stretched too
+		l.justify_last_lines();
+		let stretched = first_line_width(&l);
+		assert!(stretched > ragged);
+		assert!(stretched <= l.get_width());
+		assert_eq!(l.get_line_count(), 2);
+	}
+
+	#[test]
+	fn layout_letter_spacing() {
+		let l = create_layout_for_testing();
+		l.set_width(-1);
+		l.resize(10 * SCALE, LetterSpacing::default());
+		let plain = l.get_extents().1.width;
+		l.resize(10 * SCALE, LetterSpacing::Em(0.1));
+		let spaced = l.get_extents().1.width;
+		assert!(spaced > plain);
+		// each size replaces the spacing of the last, rather than adding to it
+		l.resize(20 * SCALE, LetterSpacing::Em(0.1));
+		assert!(l.get_extents().1.width > spaced);
+		l.resize(10 * SCALE, LetterSpacing::Em(0.1));
+		assert_eq!(l.get_extents().1.width, spaced);
+		l.resize(10 * SCALE, LetterSpacing::default());
+		assert_eq!(l.get_extents().1.width, plain);
+	}
+
+	#[test]
+	fn layout_stranded_lines() {
+		let l = create_layout_for_testing();
+		l.set_markup("Lots of words on one line and then a\nsecond paragraph");
+		let sizes = (10..60).map(|i| i * SCALE).collect::<Vec<i32>>();
+		let rules = LineRules {
+			mode: FitMode::FillBox,
+			avoid_stranded_lines: true,
+			..LineRules::default()
+		};
+		let strict = l.try_font_sizes(&sizes, rules).unwrap();
+		assert!(l.stranded_line().is_none());
+		let loose = l.try_font_sizes(&sizes, LineRules::default()).unwrap();
+		assert!(strict.largest_fit <= loose.largest_fit);
+
+		// just too narrow for the last word, which is left on a line of its own
+		l.set_width(-1);
+		l.set_font_size(10 * SCALE);
+		l.set_markup("Lots of words on one");
+		let (_, first_line) = l.get_extents();
+		l.set_width(first_line.width + SCALE / 2);
+		l.set_markup("Lots of words on one line");
+		let sizes = vec![10 * SCALE];
+		let loose = l.try_font_sizes(&sizes, LineRules::default()).unwrap();
+		assert_eq!(loose.largest_fit, Some(10 * SCALE));
+		assert_eq!(l.get_line_count(), 2);
+		let strict = l.try_font_sizes(&sizes, rules).unwrap();
+		assert_eq!(strict.largest_fit, None);
+		assert_eq!(strict.smallest_failure.unwrap().reason, FitFailureReason::Widow);
+
+		l.set_markup("Two\nwords");
+		l.set_font_size(10 * SCALE);
+		assert!(l.stranded_line().is_none());
+		l.set_width(30 * SCALE);
+		l.set_markup("Two words");
+		assert_eq!(l.get_line_count(), 2);
+		assert_eq!(l.stranded_line(), Some(FitFailureReason::Widow));
+	}
+
 	#[test]
 	fn layout_fontsizing() {
         let layout = create_layout_for_testing();
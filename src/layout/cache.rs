@@ -0,0 +1,286 @@
+use crate::errors::SvgTextBoxError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::hash::Hasher;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use glib::translate::ToGlib;
+use pango::{FontFamilyExt, FontMapExt, Layout};
+use pangocairo::FontMap;
+use siphasher::sip::SipHasher13;
+use crate::fonts::{hash_files, installed_font_files};
+use super::{LayoutManager, LayoutSource, FitReport, FitStrategy, FontRegistry, OverflowPolicy, Justification, LetterSpacing, FitMode, WrapStyle, Shape, Exclusion, TextPath, WritingMode, TextDirection};
+
+/// The outcome of fitting, which is all that is needed to lay the text out again.
+/// All units are `points * pango::SCALE`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CachedFit {
+    pub width: i32,
+    pub height: i32,
+    pub font_size: i32,
+    /// set if nothing fit, and the text was truncated
+    pub truncation: Option<OverflowPolicy>,
+}
+
+impl CachedFit {
+
+	fn from_report(report: &FitReport) -> Option<CachedFit> {
+		let chosen = report.chosen()?;
+		Some(CachedFit {
+			width: chosen.width,
+			height: chosen.height,
+			font_size: report.font_size()?,
+			truncation: report.truncation.as_ref().map(|t| t.policy)
+		})
+	}
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Entries {
+	/// a hash of the installed font families, and their files, when these fits were made
+	fonts: u64,
+	fits: HashMap<u64, CachedFit>,
+}
+
+/// Remembers the dimensions and font size chosen for each combination of markup, font,
+/// alignment, spacing and candidates, so identical textboxes are only fitted once.
+/// It can be shared between threads, and saved to disk.
+///
+/// Keys cover the size and modification time of registered font files, so fits made
+/// before one is replaced are not used again.
+/// Keys are hashed with fixed keys from a serialization of the source, so a cache saved
+/// by one build can be used by another, as long as the source serializes the same way.
+#[derive(Debug)]
+pub struct FitCache {
+	entries: Mutex<Entries>,
+	path: Option<PathBuf>,
+}
+
+impl Default for FitCache {
+	fn default() -> Self {
+		FitCache::new()
+	}
+}
+
+impl FitCache {
+
+	/// An empty cache, held only in memory
+	pub fn new() -> Self {
+		FitCache {
+			entries: Mutex::new(Entries {
+				fonts: font_fingerprint(),
+				fits: HashMap::new()
+			}),
+			path: None
+		}
+	}
+
+	/// A cache which is saved at `path`, starting with anything already saved there.
+	/// Fits made with different fonts installed are discarded.
+	pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, SvgTextBoxError> {
+		let path = path.as_ref().to_path_buf();
+		let fonts = font_fingerprint();
+		let fits = match fs::read_to_string(&path) {
+			Ok(s) => {
+				let saved: Entries = serde_json::from_str(&s)?;
+				if saved.fonts == fonts {
+					saved.fits
+				} else {
+					HashMap::new()
+				}
+			},
+			Err(ref e) if e.kind() == ErrorKind::NotFound => HashMap::new(),
+			Err(e) => return Err(SvgTextBoxError::from(e))
+		};
+		Ok(FitCache {
+			entries: Mutex::new(Entries {fonts, fits}),
+			path: Some(path)
+		})
+	}
+
+	/// Write the cache to the path it was opened from. Does nothing for an in-memory cache.
+	pub fn save(&self) -> Result<(), SvgTextBoxError> {
+		if let Some(path) = &self.path {
+			let serialized = serde_json::to_string(&*self.entries())?;
+			fs::write(path, serialized)?;
+		}
+		Ok(())
+	}
+
+	/// Forget every fit. This should be called when fonts are added or removed.
+	pub fn clear(&self) {
+		let mut entries = self.entries();
+		entries.fits.clear();
+		entries.fonts = font_fingerprint();
+	}
+
+	/// Forget every fit if the installed font families have changed since the fits were made.
+	/// Returns whether anything was forgotten.
+	pub fn clear_if_fonts_changed(&self) -> bool {
+		let fonts = font_fingerprint();
+		let mut entries = self.entries();
+		if entries.fonts == fonts {
+			return false;
+		}
+		entries.fits.clear();
+		entries.fonts = fonts;
+		true
+	}
+
+	pub fn len(&self) -> usize {
+		self.entries().fits.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.entries().fits.is_empty()
+	}
+
+	pub fn get(&self, key: u64) -> Option<CachedFit> {
+		self.entries().fits.get(&key).cloned()
+	}
+
+	pub fn insert(&self, key: u64, fit: CachedFit) {
+		self.entries().fits.insert(key, fit);
+	}
+
+	fn entries(&self) -> MutexGuard<Entries> {
+		// entries are only ever replaced whole, so a panic elsewhere cannot leave one half-written
+		self.entries.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+	}
+
+	/// Fit `src` using `manager`, unless the same source has been fitted before
+	pub(crate) fn get_best_fit(&self, src: &(impl LayoutSource + ?Sized), manager: LayoutManager) -> Result<(Layout, FitReport), SvgTextBoxError> {
+		let strategy = src.fit_strategy();
+		let key = match fit_key(src, &manager, &*strategy) {
+			Some(k) => k,
+			None => return manager.get_best_fit(&*strategy)
+		};
+		if let Some(fit) = self.get(key) {
+			return Ok(manager.apply_cached_fit(&fit));
+		}
+		let (layout, report) = manager.get_best_fit(&*strategy)?;
+		if let Some(fit) = CachedFit::from_report(&report) {
+			self.insert(key, fit);
+		}
+		Ok((layout, report))
+	}
+}
+
+/// Everything which affects how a source is fitted, serialized to be hashed
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct FitKey<'a> {
+	markup: &'a str,
+	font_description: String,
+	alignment: i32,
+	justification: Justification,
+	line_height: f64,
+	letter_spacing: LetterSpacing,
+	paragraph_spacing: f64,
+	fit_mode: FitMode,
+	avoid_stranded_lines: bool,
+	wrap_style: WrapStyle,
+	language: Option<&'a str>,
+	hyphenate: bool,
+	overflow: OverflowPolicy,
+	font_registry: Option<&'a FontRegistry>,
+	/// the registry names its files, so what is in them must be hashed as well
+	font_files: Option<u64>,
+	shape: &'a Shape,
+	exclusions: &'a [Exclusion],
+	text_path: Option<&'a TextPath>,
+	writing_mode: WritingMode,
+	direction: TextDirection,
+	strategy: String,
+	dimensions: &'a [(i32, i32)],
+	font_sizes: &'a [i32],
+}
+
+/// A hash of everything which affects how `src` is fitted by `manager`,
+/// unless `strategy` cannot be identified
+pub(crate) fn fit_key(src: &(impl LayoutSource + ?Sized), manager: &LayoutManager, strategy: &dyn FitStrategy) -> Option<u64> {
+	let key = FitKey {
+		markup: src.markup(),
+		font_description: src.font_description().to_string(),
+		alignment: src.alignment().to_glib(),
+		justification: src.justification(),
+		line_height: src.line_height(),
+		letter_spacing: src.letter_spacing(),
+		paragraph_spacing: src.paragraph_spacing(),
+		// text on a path overrides the fit mode, shape and exclusions, so the manager's are used
+		fit_mode: manager.fit_mode,
+		avoid_stranded_lines: src.avoid_stranded_lines(),
+		wrap_style: src.wrap_style(),
+		language: src.language(),
+		hyphenate: src.hyphenate(),
+		overflow: src.overflow(),
+		font_registry: src.font_registry(),
+		font_files: src.font_registry().map(FontRegistry::fingerprint),
+		shape: &manager.shape,
+		exclusions: &manager.exclusions,
+		text_path: src.text_path(),
+		writing_mode: src.writing_mode(),
+		direction: src.direction(),
+		strategy: strategy.cache_key()?,
+		dimensions: &manager.dimensions,
+		font_sizes: &manager.font_sizes,
+	};
+	let serialized = serde_json::to_string(&key).ok()?;
+	Some(stable_hash(serialized.as_bytes()))
+}
+
+/// A hash of `bytes` which is the same in every build, so that it can be saved
+pub(crate) fn stable_hash(bytes: &[u8]) -> u64 {
+	let mut hasher = SipHasher13::new_with_keys(0, 0);
+	hasher.write(bytes);
+	hasher.finish()
+}
+
+/// A hash of the names of the font families known to the default font map,
+/// and of the size and modification time of the files fontconfig found them in.
+/// Without the `fontconfig` feature only the names are hashed, so a font file
+/// which is replaced by another with the same families is not noticed.
+fn font_fingerprint() -> u64 {
+	let mut names = FontMap::get_default()
+		.map(|f| f.list_families())
+		.unwrap_or_default()
+		.iter()
+		.filter_map(|f| f.get_name())
+		.map(|n| n.to_string())
+		.collect::<Vec<String>>();
+	names.sort();
+	let mut hasher = SipHasher13::new_with_keys(0, 0);
+	for name in names {
+		hasher.write(name.as_bytes());
+		hasher.write(&[0]);
+	}
+	for file in installed_font_files() {
+		hash_files(&file, &mut hasher);
+	}
+	hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::layout::FirstAcceptable;
+	use crate::layout::tests::TestSource;
+
+	fn key(src: &TestSource) -> Option<u64> {
+		fit_key(src, &LayoutManager::new(src).unwrap(), &FirstAcceptable)
+	}
+
+	#[test]
+	fn fit_keys() {
+		// saved keys are only found again if this never changes
+		assert_eq!(stable_hash(b"svgtextbox"), 285003255407984214);
+		let src = TestSource::new(100, 50);
+		assert!(key(&src).is_some());
+		assert_eq!(key(&src), key(&TestSource::new(100, 50)));
+		assert_ne!(key(&src), key(&TestSource::new(100, 50).with_markup("Goodbye")));
+		assert_ne!(key(&src), key(&TestSource::new(120, 50)));
+	}
+}
@@ -0,0 +1,216 @@
+use crate::errors::SvgTextBoxError;
+use pango::{Layout, AttrList, EllipsizeMode};
+use std::cmp::Ordering;
+use super::{LayoutSource, LayoutExtension, RenderedTextbox, FitReport, DimensionAttempt, Orientation, create_base_layout, paragraph_gap};
+use crate::hyphenation::SOFT_HYPHEN;
+use super::interface::{FittedLayout, Justification, id_prefix};
+
+/// Text flowed through a series of linked boxes, like linked frames
+pub struct RenderedChain {
+    /// one rendered textbox for each box, in order
+    pub boxes: Vec<RenderedTextbox>,
+    /// the font size used, in `points * pango::SCALE`
+    pub font_size: i32,
+    /// any text which did not fit in the last box
+    pub overflow: Option<String>,
+}
+
+impl RenderedChain {
+
+    /// Flow the text of `src` through boxes of each (width, height) in `geometries`,
+    /// where the unit is `points * pango::SCALE`.
+    /// As for a single box, these are the sizes of the boxes on the page, once turned.
+    /// If no `font_size` is given, the largest of `src`'s possible font sizes
+    /// at which all the text fits is used; if none do, the smallest is used.
+    /// The boxes are plain rectangles, so a source with a shape or exclusions is refused.
+    pub fn new(src: &(impl LayoutSource + ?Sized), geometries: &[(i32, i32)], font_size: Option<i32>) -> Result<RenderedChain, SvgTextBoxError> {
+        if geometries.is_empty() {
+            return Err(SvgTextBoxError::NoValidWidths);
+        }
+        // the text is split into plain boxes, so could not follow a shape
+        if src.shape().is_some() || !src.exclusions().is_empty() {
+            return Err(SvgTextBoxError::ConflictingAttributes("chained boxes can't have a shape or exclusions".to_string()));
+        }
+        // as in `LayoutManager`, the lines are laid out in the box before it is turned
+        let orientation = Orientation::new(src.rotation(), src.writing_mode());
+        let geometries = geometries.iter()
+            .map(|(w, h)| orientation.layout_size(*w, *h))
+            .collect::<Vec<(i32, i32)>>();
+        let base = create_base_layout(src)?;
+        let font_size = match font_size {
+            Some(f) => f,
+            None => fitted_font_size(src, &base, &geometries)?
+        };
+        base.resize(font_size, src.letter_spacing());
+        let text = base.get_text()
+            .ok_or(SvgTextBoxError::UnexpectedNone)?
+            .to_string();
+        let (parts, overflow_index) = split_layout(&base, &geometries, src.paragraph_spacing())?;
+        let prefix = id_prefix(src);
+        let boxes = parts.into_iter()
+            .zip(geometries.iter())
+            .enumerate()
+            .map(|(i, (layout, (width, height)))| {
+                if src.justification() == Justification::JustifyAllLines {
+                    layout.justify_last_lines();
+                }
+                let report = FitReport {
+                    attempts: vec![DimensionAttempt {
+                        width: *width,
+                        height: *height,
+                        largest_fit: Some(font_size),
+                        smallest_failure: None
+                    }],
+                    chosen: Some(0),
+                    truncation: None,
+                    cached: false,
+                    flowed: false
+                };
+                // each box is placed like a textbox of its own, with the source's padding and alignment
+                let fitted = FittedLayout::in_box(src, layout, report)
+                    .turned(orientation);
+                RenderedTextbox::from_fitted(fitted, src.text_mode(), &format!("{}box{}-", prefix, i))
+            })
+            .collect::<Result<Vec<RenderedTextbox>, SvgTextBoxError>>()?;
+        Ok(RenderedChain {
+            boxes,
+            font_size,
+            // the hyphenation points are not part of the text
+            overflow: overflow_index.map(|i| text[i..].replace(SOFT_HYPHEN, ""))
+        })
+    }
+}
+
+/// The largest possible font size at which all the text flows through `geometries`
+fn fitted_font_size(src: &(impl LayoutSource + ?Sized), base: &Layout, geometries: &[(i32, i32)]) -> Result<i32, SvgTextBoxError> {
+    let font_sizes = src.possible_font_sizes()
+        .collect::<std::collections::BTreeSet<i32>>()
+        .into_iter()
+        .collect::<Vec<i32>>();
+    let smallest = *font_sizes.first()
+        .ok_or(SvgTextBoxError::NoValidFontSizes)?;
+    // as in `grow_to_maximum_font_size`, this search always gives the index
+    // at which sizes stop fitting
+    let search_result = font_sizes.binary_search_by(|n| {
+        base.resize(*n, src.letter_spacing());
+        match split_layout(base, geometries, src.paragraph_spacing()) {
+            Ok((_, None)) => Ordering::Less,
+            _ => Ordering::Greater
+        }
+    });
+    let index = search_result.err()
+        .ok_or(SvgTextBoxError::UnexpectedNone)?;
+    let size = index.checked_sub(1)
+        .and_then(|i| font_sizes.get(i))
+        .cloned()
+        .unwrap_or(smallest);
+    Ok(size)
+}
+
+/// Split `base` at line boundaries into one layout for each of `geometries`,
+/// along with the byte index of the start of any text left over.
+/// Each paragraph is followed by `paragraph_spacing` lines of space.
+fn split_layout(base: &Layout, geometries: &[(i32, i32)], paragraph_spacing: f64) -> Result<(Vec<Layout>, Option<usize>), SvgTextBoxError> {
+    let text = base.get_text()
+        .ok_or(SvgTextBoxError::UnexpectedNone)?
+        .to_string();
+    let attrs = base.get_attributes();
+    let gap = paragraph_gap(base, paragraph_spacing);
+    let mut offset = 0;
+    let mut parts = Vec::new();
+    for (width, height) in geometries {
+        let remaining = sub_layout(base, &text, attrs.as_ref(), offset, text.len())?;
+        remaining.set_width(*width);
+        remaining.set_height(*height);
+        let end = match remaining.hidden_text_index(gap) {
+            Some(i) => offset + i as usize,
+            None => text.len()
+        };
+        let part = sub_layout(base, &text, attrs.as_ref(), offset, end)?;
+        part.set_width(*width);
+        part.set_height(*height);
+        parts.push(part);
+        offset = end;
+    }
+    let overflow = if offset < text.len() {
+        Some(offset)
+    } else {
+        None
+    };
+    Ok((parts, overflow))
+}
+
+/// A copy of `base` holding only the text between byte indices `start` and `end`
+pub(super) fn sub_layout(base: &Layout, text: &str, attrs: Option<&AttrList>, start: usize, end: usize) -> Result<Layout, SvgTextBoxError> {
+    let layout = base.copy()
+        .ok_or(SvgTextBoxError::UnexpectedNone)?;
+    // text which does not fit should move on rather than be ellipsized
+    layout.set_ellipsize(EllipsizeMode::None);
+    set_sub_text(&layout, text, attrs, start, end);
+    Ok(layout)
+}
+
+/// Give `layout` only the text between byte indices `start` and `end`, and its attributes
+pub(super) fn set_sub_text(layout: &Layout, text: &str, attrs: Option<&AttrList>, start: usize, end: usize) {
+    layout.set_text(&text[start..end]);
+    let shifted = attrs.map(|a| shift_attributes(a, start, end));
+    layout.set_attributes(shifted.as_ref());
+}
+
+/// The attributes of `attrs` which cover `start..end`, moved to begin at zero
+fn shift_attributes(attrs: &AttrList, start: usize, end: usize) -> AttrList {
+    let mut all = Vec::new();
+    attrs.filter(|a| {
+        all.push(a.clone());
+        false
+    });
+    let (start, end) = (start as u32, end as u32);
+    let shifted = AttrList::new();
+    for mut a in all {
+        if a.get_end_index() <= start || a.get_start_index() >= end {
+            continue;
+        }
+        let s = a.get_start_index().max(start) - start;
+        let e = a.get_end_index().min(end) - start;
+        a.set_start_index(s);
+        a.set_end_index(e);
+        shifted.insert(a);
+    }
+    shifted
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::layout::tests::TestSource;
+	use pango::SCALE;
+
+	#[test]
+	fn rendered_chain() {
+		let src = TestSource::new(100, 100)
+			.with_markup("Hello <b>World</b> and hello again to everyone else in the world");
+		let geometries = vec![(100 * SCALE, 30 * SCALE), (100 * SCALE, 200 * SCALE)];
+
+		let chain = RenderedChain::new(&src, &geometries, Some(12 * SCALE)).unwrap();
+		assert_eq!(chain.boxes.len(), 2);
+		assert_eq!(chain.font_size, 12 * SCALE);
+		assert!(chain.overflow.is_none());
+
+		let chain = RenderedChain::new(&src, &geometries[..1], Some(12 * SCALE)).unwrap();
+		let overflow = chain.overflow.unwrap();
+		assert!(overflow.ends_with("in the world"));
+		assert!(!overflow.starts_with("Hello"));
+
+		let chain = RenderedChain::new(&src, &geometries, None).unwrap();
+		assert!(chain.overflow.is_none());
+		assert!(chain.font_size > 12 * SCALE);
+
+		// the last line of the text, in the last box, is only stretched when all lines are
+		let justified = RenderedChain::new(&src.with_justification(Justification::Justify), &geometries, Some(12 * SCALE)).unwrap();
+		let src = TestSource::new(100, 100)
+			.with_markup("Hello <b>World</b> and hello again to everyone else in the world")
+			.with_justification(Justification::JustifyAllLines);
+		let all_lines = RenderedChain::new(&src, &geometries, Some(12 * SCALE)).unwrap();
+		assert_ne!(justified.boxes[1].src, all_lines.boxes[1].src);
+	}
+}
@@ -0,0 +1,786 @@
+use crate::errors::SvgTextBoxError;
+use std::collections::{HashMap, BTreeMap};
+use std::ffi::CString;
+use serde::Serialize;
+use pango::{SCALE, Alignment, FontDescription};
+use pango::Layout;
+use cairo::{Antialias, Format};
+use super::{LayoutManager, FitReport, FitStrategy, FirstAcceptable, FitMode, WrapStyle, SvgTextMode, VerticalAlignment, OverflowPolicy, FitCache, FontRegistry, Shape, Exclusion, TextPath, WritingMode, TextDirection, Orientation, LetterSpacing, paragraph_offsets, paragraph_gap};
+use super::live_text::to_live_text_svg;
+
+/// An implementation of this trait can be used to generate a layout
+pub trait LayoutSource {
+    /// All possible font sizes, where the unit is `points * pango::SCALE`
+    /// This should be ordered.
+    fn possible_font_sizes<'a>(&'a self) -> Box<dyn Iterator<Item=i32> + 'a>;
+    /// All possible heights (in order of preference); the unit should be `points * pango::SCALE`
+    fn possible_heights<'a>(&'a self) -> Box<dyn Iterator<Item=i32> + 'a>;
+    /// All possible widths (in order of preference); the unit should be `points * pango::SCALE`
+    fn possible_widths<'a>(&'a self) -> Box<dyn Iterator<Item=i32> + 'a>;
+    /// The font description of the text as a whole
+    fn font_description(&self) -> &FontDescription;
+    /// The text to set
+    fn markup(&self) -> &str;
+    /// The alignment of the text
+    fn alignment(&self) -> Alignment;
+    /// The language of the text, as a tag such as `de` or `nl-BE` (defaults to none)
+    fn language(&self) -> Option<&str> {
+        None
+    }
+    /// Whether to insert soft hyphens so words can be broken across lines,
+    /// using the patterns for `language` (defaults to false)
+    fn hyphenate(&self) -> bool {
+        false
+    }
+    /// Whether the text should be justified (defaults to not)
+    fn justification(&self) -> Justification {
+        Justification::None
+    }
+    /// The distance between baselines as a multiple of the natural line height (defaults to 1.0)
+    fn line_height(&self) -> f64 {
+        1.0
+    }
+    /// Extra space between letters, which may depend on the font size (defaults to none)
+    fn letter_spacing(&self) -> LetterSpacing {
+        LetterSpacing::default()
+    }
+    /// Extra space between paragraphs, as a multiple of the line height (defaults to 0.0)
+    fn paragraph_spacing(&self) -> f64 {
+        0.0
+    }
+    /// How to choose between the possible widths and heights (defaults to
+    /// the first pair at which any font size fits)
+    fn fit_strategy<'a>(&'a self) -> Box<dyn FitStrategy + 'a> {
+        Box::new(FirstAcceptable)
+    }
+    /// Any constraint on the number of lines, as well as fitting the box (defaults to none)
+    fn fit_mode(&self) -> FitMode {
+        FitMode::FillBox
+    }
+    /// Whether to reject font sizes which leave a paragraph with a single word on
+    /// its first or last line (defaults to false)
+    fn avoid_stranded_lines(&self) -> bool {
+        false
+    }
+    /// How to break text into lines once the font size is chosen (defaults to greedy)
+    fn wrap_style(&self) -> WrapStyle {
+        WrapStyle::Greedy
+    }
+    // the image output width as distinct from the textbox width (defaults to textbox width)
+    fn output_width(&self, layout_width: i32) -> f64 {
+        f64::from(layout_width) / f64::from(SCALE)
+    }
+    /// the image output height as distinct from the textbox height (defaults to textbox height)
+    fn output_height(&self, layout_height: i32) -> f64 {
+        f64::from(layout_height) / f64::from(SCALE)
+    }
+    /// the x-coordinate to place the textbox on the surface (defaults to 0.0)
+    fn output_x(&self) -> f64 {
+        0.0
+    }
+    /// the y-coordinate to place the textbox on the surface (defaults to 0.0)
+    fn output_y(&self) -> f64 {
+        0.0
+    }
+    /// how text should be written in svg output (defaults to glyph outlines); live text
+    /// can't be vertical, justified or letter spaced
+    fn text_mode(&self) -> SvgTextMode {
+        SvgTextMode::Outlines
+    }
+    /// attributes of a rectangle drawn behind the whole image, on every surface (defaults to none)
+    fn background_attrs(&self) -> Option<&HashMap<String, String>> {
+        None
+    }
+    /// where to place the rendered layout on the image surface if it does not fill
+    /// the height available (defaults to centring the inked text)
+    fn vertical_alignment(&self) -> VerticalAlignment {
+        VerticalAlignment::MiddleInk
+    }
+    /// what to do if the text does not fit at any font size (defaults to failing)
+    fn overflow(&self) -> OverflowPolicy {
+        OverflowPolicy::Error
+    }
+    /// somewhere to look up and remember fits, to avoid fitting the same text twice (defaults to none)
+    fn fit_cache(&self) -> Option<&FitCache> {
+        None
+    }
+    /// fonts to use as well as those installed (defaults to none)
+    fn font_registry(&self) -> Option<&FontRegistry> {
+        None
+    }
+    /// the shape the text is set inside, if not the whole box (defaults to none)
+    fn shape(&self) -> Option<&Shape> {
+        None
+    }
+    /// areas inside the box which the text flows around (defaults to none)
+    fn exclusions(&self) -> &[Exclusion] {
+        &[]
+    }
+    /// a path the text follows as a single line, instead of being set in the box (defaults to none)
+    fn text_path(&self) -> Option<&TextPath> {
+        None
+    }
+    /// how many degrees clockwise the text is turned on the page (defaults to none)
+    fn rotation(&self) -> f64 {
+        0.0
+    }
+    /// which way lines of text run (defaults to horizontal)
+    fn writing_mode(&self) -> WritingMode {
+        WritingMode::Horizontal
+    }
+    /// the direction text runs in, which should agree with `alignment` (defaults to automatic)
+    fn direction(&self) -> TextDirection {
+        TextDirection::Auto
+    }
+    /// begins the ids of elements in svg output, so that several images can share
+    /// one document (defaults to none)
+    fn svg_id(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// What to put before each id in the svg output of `src`
+pub(crate) fn id_prefix(src: &(impl LayoutSource + ?Sized)) -> String {
+    src.svg_id()
+        .map(|id| format!("{}-", id))
+        .unwrap_or_default()
+}
+
+/// Which lines of text are stretched to fill the width of the layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Justification {
+    None,
+    /// every line except the last of each paragraph
+    Justify,
+    /// every line, including the last of each paragraph
+    JustifyAllLines,
+}
+
+/// A rendered layout, with information about its final width and height
+pub struct RenderedTextbox {
+    pub src: String,
+    pub width: f64,
+    pub height: f64,
+    /// how the final layout was chosen
+    pub report: FitReport,
+}
+
+/// A layout which has been fitted, along with where it should be drawn
+pub(crate) struct FittedLayout {
+    pub(crate) layout: Layout,
+    pub(crate) report: FitReport,
+    pub(crate) width: f64,
+    pub(crate) height: f64,
+    pub(crate) x: f64,
+    pub(crate) y: f64,
+    /// the box outside which nothing is drawn, as (x, y, width, height), if clipped
+    pub(crate) clip: Option<(f64, f64, f64, f64)>,
+    /// the path the text follows, if any, which starts from (x, y) rather than the box
+    pub(crate) path: Option<TextPath>,
+    /// how the box is turned on the page; `width` and `height` are those of the turned box
+    pub(crate) orientation: Orientation,
+    /// the width and height of the box before it is turned
+    pub(crate) upright_size: (f64, f64),
+    /// how far each line is moved down by the space between paragraphs,
+    /// in `points * pango::SCALE`; empty if no line moves
+    pub(crate) paragraph_offsets: Vec<i32>,
+}
+
+impl FittedLayout {
+
+    pub(crate) fn new(src: &(impl LayoutSource + ?Sized)) -> Result<FittedLayout, SvgTextBoxError> {
+        let manager = LayoutManager::new(src)?;
+        let (layout, report) = match src.fit_cache() {
+            Some(cache) => cache.get_best_fit(src, manager)?,
+            None => manager.get_best_fit(&*src.fit_strategy())?
+        };
+        let fitted = match src.text_path() {
+            Some(path) => FittedLayout::on_path(src, layout, report, path),
+            None => FittedLayout::in_box(src, layout, report)
+        };
+        Ok(fitted.turned(Orientation::new(src.rotation(), src.writing_mode())))
+    }
+
+    pub(crate) fn in_box(src: &(impl LayoutSource + ?Sized), layout: Layout, report: FitReport) -> FittedLayout {
+        // the layout may have been narrowed to balance its lines, but the box keeps its width
+        let box_width = report.chosen()
+            .map(|a| a.width)
+            .unwrap_or_else(|| layout.get_width());
+        let width = src.output_width(box_width);
+        let height = src.output_height(layout.get_height());
+        let x = src.output_x() + horizontal_offset(&layout, box_width);
+        let clipped = report.truncation.as_ref()
+            .map(|t| t.policy == OverflowPolicy::Clip)
+            .unwrap_or(false);
+        // text flowed into a shape or around exclusions was placed vertically as its lines
+        // were set, with its paragraphs already apart
+        let placed = report.flowed;
+        let paragraph_offsets = if placed {
+            Vec::new()
+        } else {
+            paragraph_offsets(&layout, paragraph_gap(&layout, src.paragraph_spacing()))
+        };
+        let mut vertical_offset = if placed {
+            0.0
+        } else {
+            let paragraph_space = paragraph_offsets.last().cloned().unwrap_or(0);
+            src.vertical_alignment().offset(&layout, paragraph_space)
+        };
+        let clip = if clipped {
+            // text which overflows is cut off at the bottom, so always starts in the box
+            vertical_offset = vertical_offset.max(0.0);
+            Some((
+                src.output_x(),
+                src.output_y(),
+                f64::from(box_width) / f64::from(SCALE),
+                f64::from(layout.get_height()) / f64::from(SCALE)
+            ))
+        } else {
+            None
+        };
+    	let y = src.output_y() + vertical_offset;
+    	FittedLayout {
+    		layout,
+    		report,
+    		width,
+    		height,
+    		x,
+    		y,
+    		clip,
+    		path: None,
+    		orientation: Orientation::default(),
+    		upright_size: (width, height),
+    		paragraph_offsets
+    	}
+    }
+
+    /// The text sits on `path`, and the box reaches far enough right and down to hold it,
+    /// leaving room for glyphs as tall as the layout beyond the path.
+    /// Glyphs which would rise above or left of the box grow it that way instead,
+    /// moving the path down and right within it.
+    fn on_path(src: &(impl LayoutSource + ?Sized), layout: Layout, report: FitReport, path: &TextPath) -> FittedLayout {
+        let (left, top) = path.top_left();
+        let (right, bottom) = path.bottom_right();
+        let room = f64::from(layout.get_height());
+        let ascent = f64::from(layout.get_baseline());
+        let scale = f64::from(SCALE);
+        let before_x = (ascent - left * scale).max(0.0);
+        let before_y = (ascent - top * scale).max(0.0);
+        let width = src.output_width((right * scale + room + before_x) as i32);
+        let height = src.output_height((bottom * scale + room + before_y) as i32);
+        FittedLayout {
+            width,
+            height,
+            x: src.output_x() + before_x / scale,
+            y: src.output_y() + before_y / scale,
+            layout,
+            report,
+            clip: None,
+            path: Some(path.clone()),
+            orientation: Orientation::default(),
+            upright_size: (width, height),
+            paragraph_offsets: Vec::new()
+        }
+    }
+
+    /// The same layout with its box turned, so that it is as wide and high as the turned box
+    pub(crate) fn turned(mut self, orientation: Orientation) -> FittedLayout {
+        let (width, height) = self.upright_size;
+        let (turned_width, turned_height) = orientation.bounding_size(width, height);
+        self.width = turned_width;
+        self.height = turned_height;
+        self.orientation = orientation;
+        self
+    }
+
+    /// Draw the layout onto `context`, in surface units
+    pub(crate) fn draw(&self, context: &cairo::Context) {
+        context.save();
+        let (width, height) = self.upright_size;
+        self.orientation.apply(context, width, height);
+        if let Some((x, y, width, height)) = self.clip {
+            context.rectangle(x, y, width, height);
+            context.clip();
+        }
+        match &self.path {
+            Some(path) => path.draw(context, &self.layout, self.x, self.y),
+            None if !self.paragraph_offsets.is_empty() => self.draw_lines(context),
+            None => {
+                context.move_to(self.x, self.y);
+                pangocairo::functions::show_layout(context, &self.layout);
+            }
+        }
+        context.restore();
+    }
+
+    /// Draw each line on its own, as pango does, but moved down by its paragraph offset
+    fn draw_lines(&self, context: &cairo::Context) {
+        let mut iter = match self.layout.get_iter() {
+            Some(i) => i,
+            None => return
+        };
+        let scale = f64::from(SCALE);
+        let mut index = 0;
+        loop {
+            let (_ink, logical) = iter.get_line_extents();
+            let offset = self.paragraph_offsets.get(index).cloned().unwrap_or(0);
+            if let Some(line) = iter.get_line_readonly() {
+                context.move_to(
+                    self.x + f64::from(logical.x) / scale,
+                    self.y + f64::from(iter.get_baseline() + offset) / scale
+                );
+                pangocairo::functions::show_layout_line(context, &line);
+            }
+            if !iter.next_line() {
+                break;
+            }
+            index += 1;
+        }
+    }
+}
+
+/// How far right to move `layout` to keep its alignment within a box `box_width` wide, in points
+fn horizontal_offset(layout: &Layout, box_width: i32) -> f64 {
+    let spare = box_width - layout.get_width();
+    let offset = match layout.get_alignment() {
+        Alignment::Center => spare / 2,
+        Alignment::Right => spare,
+        _ => 0
+    };
+    f64::from(offset) / f64::from(SCALE)
+}
+
+impl RenderedTextbox {
+
+	/// Create a new svg image from `src`
+    pub fn new(src: &(impl LayoutSource + ?Sized)) -> Result<RenderedTextbox, SvgTextBoxError> {
+        RenderedTextbox::with_text_mode(src, src.text_mode())
+    }
+
+    /// Create a new svg image from `src`, writing text as `mode`
+    pub fn with_text_mode(src: &(impl LayoutSource + ?Sized), mode: SvgTextMode) -> Result<RenderedTextbox, SvgTextBoxError> {
+        let fitted = FittedLayout::new(src)?;
+        RenderedTextbox::from_fitted(fitted, mode, &id_prefix(src))
+    }
+
+    /// Create a new svg image from a layout which has already been fitted,
+    /// beginning any ids in it with `id_prefix`
+    pub(crate) fn from_fitted(fitted: FittedLayout, mode: SvgTextMode, id_prefix: &str) -> Result<RenderedTextbox, SvgTextBoxError> {
+        if mode == SvgTextMode::LiveText {
+            let svg = to_live_text_svg(&fitted, id_prefix)?;
+            return Ok(RenderedTextbox {
+                src: svg,
+                width: fitted.width,
+                height: fitted.height,
+                report: fitted.report
+            });
+        }
+        let writable = Vec::new();
+        let surface = cairo::SvgSurface::for_stream(fitted.width, fitted.height, writable);
+        let context = cairo::Context::new(&surface);
+        fitted.draw(&context);
+        let image_bytes = surface
+            .finish_output_stream()?
+            .downcast::<Vec<u8>>()
+            .map(|v| v.to_vec())
+            .map_err(|_| SvgTextBoxError::OutputStream)?;
+        let svg = std::str::from_utf8(&image_bytes)?;
+        let image = RenderedTextbox {
+        	src: svg.to_string(),
+        	width: fitted.width,
+        	height: fitted.height,
+        	report: fitted.report
+        };
+        Ok(image)
+    }
+
+    /// Insert a background rectangle into the created svg image.
+    pub fn insert_background_rect(&mut self, attrs: &HashMap<String, String>) -> Result<&mut Self, SvgTextBoxError> {
+        let mut a = attrs.iter()
+        	.map(|(k, v)| (k.as_str(), v.to_string()))
+        	.collect::<BTreeMap<&str, String>>();
+        a.insert("x", "0".to_string());
+        a.insert("y", "0".to_string());
+        a.insert("width", self.width.to_string());
+        a.insert("height", self.height.to_string());
+        let b = a.iter()
+        	.map(|(k, v)| format!("{}=\"{}\"", k, v))
+			.collect::<Vec<String>>()
+			.join(" ");
+		let padding_rect = format!("</defs>\n<g>\n<rect {}/>\n</g>", b);
+					
+        self.src = self.src
+        	.replace("</defs>", &padding_rect);
+        Ok(self)
+    }
+}
+
+impl AsRef<str> for RenderedTextbox {
+    fn as_ref(&self) -> &str {
+        self.src.as_str()
+    }
+}
+
+impl std::fmt::Display for RenderedTextbox {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.src)
+    }
+}
+
+/// Options for rasterising a layout
+#[derive(Debug, Clone, PartialEq)]
+pub struct PngOptions {
+    /// pixels per point (defaults to 1.0, i.e. 72 dpi)
+    pub scale: f64,
+    /// an rgba background; if `None` the background is transparent
+    pub background: Option<(f64, f64, f64, f64)>,
+    /// the antialiasing mode; if `None` cairo's default is used
+    pub antialias: Option<Antialias>,
+}
+
+impl PngOptions {
+    /// Options to rasterise at `dpi` dots per inch
+    pub fn with_dpi(dpi: f64) -> Self {
+        PngOptions {
+            scale: dpi / 72.0,
+            ..PngOptions::default()
+        }
+    }
+}
+
+impl Default for PngOptions {
+    fn default() -> Self {
+        PngOptions {
+            scale: 1.0,
+            background: None,
+            antialias: None
+        }
+    }
+}
+
+/// A rasterised layout, as png bytes
+pub struct RenderedPng {
+    pub data: Vec<u8>,
+    /// the width of the image in pixels
+    pub pixel_width: i32,
+    /// the height of the image in pixels
+    pub pixel_height: i32,
+    /// how the final layout was chosen
+    pub report: FitReport,
+}
+
+impl RenderedPng {
+
+    /// Create a new png image from `src`
+    pub fn new(src: &(impl LayoutSource + ?Sized), options: &PngOptions) -> Result<RenderedPng, SvgTextBoxError> {
+        check_scale(options)?;
+        let fitted = FittedLayout::new(src)?;
+        RenderedPng::from_fitted(fitted, src.background_attrs(), options)
+    }
+
+    /// Draw `fitted` over `background`, if there is one
+    fn from_fitted(fitted: FittedLayout, background: Option<&HashMap<String, String>>, options: &PngOptions) -> Result<RenderedPng, SvgTextBoxError> {
+        let pixel_width = (fitted.width * options.scale).ceil() as i32;
+        let pixel_height = (fitted.height * options.scale).ceil() as i32;
+        let surface = cairo::ImageSurface::create(Format::ARgb32, pixel_width, pixel_height)?;
+        let context = cairo::Context::new(&surface);
+        if let Some(antialias) = options.antialias {
+            context.set_antialias(antialias);
+        }
+        if let Some((r, g, b, a)) = options.background {
+            context.set_source_rgba(r, g, b, a);
+            context.paint();
+        }
+        context.scale(options.scale, options.scale);
+        if let Some(attrs) = background {
+            draw_background(&context, attrs, fitted.width, fitted.height);
+        }
+        context.set_source_rgb(0.0, 0.0, 0.0);
+        fitted.draw(&context);
+        surface.flush();
+        let mut data = Vec::new();
+        surface.write_to_png(&mut data)?;
+        Ok(RenderedPng {
+            data,
+            pixel_width,
+            pixel_height,
+            report: fitted.report
+        })
+    }
+}
+
+/// Fail unless `options` scales by a positive, finite amount
+fn check_scale(options: &PngOptions) -> Result<(), SvgTextBoxError> {
+    if !options.scale.is_finite() || options.scale <= 0.0 {
+        return Err(SvgTextBoxError::InvalidScale(options.scale));
+    }
+    Ok(())
+}
+
+impl AsRef<[u8]> for RenderedPng {
+    fn as_ref(&self) -> &[u8] {
+        self.data.as_slice()
+    }
+}
+
+/// The formats a fitted layout can be rendered to
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputFormat {
+    Svg,
+    Png(PngOptions),
+    Pdf,
+    Ps,
+}
+
+impl OutputFormat {
+    /// Whether this format can hold more than one page
+    pub fn is_paged(&self) -> bool {
+        match self {
+            OutputFormat::Pdf | OutputFormat::Ps => true,
+            OutputFormat::Svg | OutputFormat::Png(_) => false
+        }
+    }
+}
+
+/// One or more fitted layouts rendered to bytes, one per page
+pub struct RenderedDocument {
+    pub data: Vec<u8>,
+    pub format: OutputFormat,
+    /// the (width, height) of each page, in points
+    pub page_sizes: Vec<(f64, f64)>,
+    /// how the layout on each page was chosen
+    pub reports: Vec<FitReport>,
+}
+
+impl RenderedDocument {
+
+    /// Render `src` as a single page document
+    pub fn new(src: &(impl LayoutSource + ?Sized), format: &OutputFormat) -> Result<RenderedDocument, SvgTextBoxError> {
+        let (data, page_size, report) = match format {
+            OutputFormat::Svg => {
+                let mut r = RenderedTextbox::new(src)?;
+                if let Some(attrs) = src.background_attrs() {
+                    r.insert_background_rect(attrs)?;
+                }
+                (r.src.into_bytes(), (r.width, r.height), r.report)
+            },
+            OutputFormat::Png(options) => {
+                check_scale(options)?;
+                let fitted = FittedLayout::new(src)?;
+                // the textbox's own size, as the pixel sizes are rounded up
+                let page_size = (fitted.width, fitted.height);
+                let r = RenderedPng::from_fitted(fitted, src.background_attrs(), options)?;
+                (r.data, page_size, r.report)
+            },
+            OutputFormat::Pdf | OutputFormat::Ps => {
+                return RenderedDocument::from_pages(&[src], format);
+            }
+        };
+        Ok(RenderedDocument {
+            data,
+            format: format.clone(),
+            page_sizes: vec![page_size],
+            reports: vec![report]
+        })
+    }
+
+    /// Render each of `sources` on its own page, with each page sized to its textbox.
+    /// Only paged formats can hold more than one source.
+    pub fn from_pages<S: LayoutSource + ?Sized>(sources: &[&S], format: &OutputFormat) -> Result<RenderedDocument, SvgTextBoxError> {
+        if sources.is_empty() {
+            return Err(SvgTextBoxError::NoPages);
+        }
+        if !format.is_paged() {
+            if sources.len() > 1 {
+                return Err(SvgTextBoxError::PagesUnsupported);
+            }
+            return RenderedDocument::new(sources[0], format);
+        }
+        let fitted = sources.iter()
+            .map(|src| FittedLayout::new(*src))
+            .collect::<Result<Vec<FittedLayout>, SvgTextBoxError>>()?;
+        let backgrounds = sources.iter()
+            .map(|src| src.background_attrs())
+            .collect::<Vec<Option<&HashMap<String, String>>>>();
+        let (first_width, first_height) = (fitted[0].width, fitted[0].height);
+        let writable = Vec::new();
+        let data = match format {
+            OutputFormat::Pdf => {
+                let surface = cairo::PdfSurface::for_stream(first_width, first_height, writable);
+                draw_pages(&surface, &fitted, &backgrounds, |w, h| surface.set_size(w, h))?
+            },
+            OutputFormat::Ps => {
+                let surface = cairo::PsSurface::for_stream(first_width, first_height, writable);
+                draw_pages(&surface, &fitted, &backgrounds, |w, h| surface.set_size(w, h))?
+            },
+            _ => unreachable!()
+        };
+        let page_sizes = fitted.iter()
+            .map(|f| (f.width, f.height))
+            .collect();
+        let reports = fitted.into_iter()
+            .map(|f| f.report)
+            .collect();
+        Ok(RenderedDocument {
+            data,
+            format: format.clone(),
+            page_sizes,
+            reports
+        })
+    }
+}
+
+/// Draw each layout onto its own page of `surface`, over its background if it has one,
+/// resizing pages with `set_size`
+fn draw_pages<F>(surface: &cairo::Surface, fitted: &[FittedLayout], backgrounds: &[Option<&HashMap<String, String>>], set_size: F) -> Result<Vec<u8>, SvgTextBoxError>
+    where F: Fn(f64, f64)
+{
+    let context = cairo::Context::new(surface);
+    for (page, background) in fitted.iter().zip(backgrounds.iter()) {
+        // the page size can only be changed before anything is drawn on it
+        set_size(page.width, page.height);
+        if let Some(attrs) = background {
+            draw_background(&context, attrs, page.width, page.height);
+        }
+        context.set_source_rgb(0.0, 0.0, 0.0);
+        page.draw(&context);
+        context.show_page();
+    }
+    let bytes = surface
+        .finish_output_stream()?
+        .downcast::<Vec<u8>>()
+        .map(|v| v.to_vec())
+        .map_err(|_| SvgTextBoxError::OutputStream)?;
+    Ok(bytes)
+}
+
+/// Draw the rectangle svg output puts behind the text, `width` by `height` from the origin,
+/// with the fill and stroke given in `attrs`. As in svg, the fill is black unless given,
+/// and there is no stroke unless given. Other attributes are only kept in svg output.
+pub(crate) fn draw_background(context: &cairo::Context, attrs: &HashMap<String, String>, width: f64, height: f64) {
+    let number = |name: &str, default: f64| attrs.get(name)
+        .and_then(|v| v.trim().parse::<f64>().ok())
+        .unwrap_or(default);
+    let opacity = number("opacity", 1.0);
+    let fill = match attrs.get("fill") {
+        Some(f) => parse_paint(f),
+        None => Some((0.0, 0.0, 0.0))
+    };
+    let stroke = attrs.get("stroke").and_then(|s| parse_paint(s));
+    context.save();
+    context.new_path();
+    context.rectangle(0.0, 0.0, width, height);
+    if let Some((r, g, b)) = fill {
+        context.set_source_rgba(r, g, b, opacity * number("fill-opacity", 1.0));
+        context.fill_preserve();
+    }
+    if let Some((r, g, b)) = stroke {
+        context.set_source_rgba(r, g, b, opacity * number("stroke-opacity", 1.0));
+        context.set_line_width(number("stroke-width", 1.0));
+        context.stroke_preserve();
+    }
+    context.new_path();
+    context.restore();
+}
+
+/// An svg paint as rgb from 0 to 1, or None for no paint or one which can't be read
+fn parse_paint(value: &str) -> Option<(f64, f64, f64)> {
+    let value = value.trim();
+    if value == "none" || value == "transparent" {
+        return None;
+    }
+    let spec = CString::new(value).ok()?;
+    let mut color = pango_sys::PangoColor {red: 0, green: 0, blue: 0};
+    // pango reads the same hex colours and colour names as svg
+    let parsed = unsafe { pango_sys::pango_color_parse(&mut color, spec.as_ptr()) };
+    if parsed == 0 {
+        return None;
+    }
+    let channel = |c: u16| f64::from(c) / f64::from(u16::MAX);
+    Some((channel(color.red), channel(color.green), channel(color.blue)))
+}
+
+impl AsRef<[u8]> for RenderedDocument {
+    fn as_ref(&self) -> &[u8] {
+        self.data.as_slice()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::layout::tests::TestSource;
+
+	#[test]
+	fn rendered_textbox_display() {
+		let tb = RenderedTextbox{
+			src: "Test".to_string(),
+			width: 100.0,
+			height: 100.0,
+			report: FitReport::default()
+		};
+
+		assert_eq!(tb.to_string(), tb.src);
+		assert_eq!(tb.as_ref(), tb.src);
+	}
+
+	#[test]
+	fn rendered_png() {
+		let src = TestSource::new(100, 50);
+		let png = RenderedPng::new(&src, &PngOptions::default()).unwrap();
+		assert_eq!((png.pixel_width, png.pixel_height), (100, 50));
+		assert!(png.data.starts_with(b"\x89PNG"));
+		let options = PngOptions {
+			background: Some((1.0, 1.0, 1.0, 1.0)),
+			antialias: Some(cairo::Antialias::None),
+			..PngOptions::with_dpi(144.0)
+		};
+		let png = RenderedPng::new(&src, &options).unwrap();
+		assert_eq!((png.pixel_width, png.pixel_height), (200, 100));
+		assert!(png.report.chosen().is_some());
+	}
+
+	#[test]
+	fn rendered_document() {
+		let small = TestSource::new(100, 50);
+		let large = TestSource::new(200, 300);
+		let pages: Vec<&dyn LayoutSource> = vec![&small, &large];
+
+		let pdf = RenderedDocument::from_pages(&pages, &OutputFormat::Pdf).unwrap();
+		assert!(pdf.data.starts_with(b"%PDF"));
+		assert_eq!(pdf.page_sizes, vec![(100.0, 50.0), (200.0, 300.0)]);
+		assert_eq!(pdf.reports.len(), 2);
+
+		let ps = RenderedDocument::from_pages(&pages, &OutputFormat::Ps).unwrap();
+		assert!(ps.data.starts_with(b"%!PS"));
+
+		let svg = RenderedDocument::new(&small, &OutputFormat::Svg).unwrap();
+		assert_eq!(svg.page_sizes, vec![(100.0, 50.0)]);
+		let png = RenderedDocument::new(&small, &OutputFormat::Png(PngOptions::with_dpi(144.0))).unwrap();
+		assert_eq!(png.page_sizes, vec![(100.0, 50.0)]);
+		// a scale which leaves part of a pixel over does not change the page
+		let png = RenderedDocument::new(&small, &OutputFormat::Png(PngOptions::with_dpi(100.0))).unwrap();
+		assert_eq!(png.page_sizes, vec![(100.0, 50.0)]);
+		assert!(RenderedDocument::from_pages(&pages, &OutputFormat::Svg).is_err());
+		let no_pages: Vec<&dyn LayoutSource> = Vec::new();
+		assert!(RenderedDocument::from_pages(&no_pages, &OutputFormat::Pdf).is_err());
+	}
+
+	#[test]
+	fn new_rendered_textbox() {
+		let rendered = RenderedTextbox::new(&TestSource::new(100, 50)).unwrap();
+		assert_eq!((rendered.width, rendered.height), (100.0, 50.0));
+		assert!(rendered.src.contains("<svg"));
+		assert!(rendered.report.font_size().is_some());
+	}
+
+	#[test]
+	fn insert_background_rect() {
+		let mut rendered = RenderedTextbox::new(&TestSource::new(100, 50)).unwrap();
+		let mut attrs = std::collections::HashMap::new();
+		attrs.insert("fill".to_string(), "red".to_string());
+		rendered.insert_background_rect(&attrs).unwrap();
+		assert!(rendered.src.contains("<rect fill=\"red\" height=\"50\" width=\"100\" x=\"0\" y=\"0\"/>"));
+	}
+}
@@ -0,0 +1,153 @@
+use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
+use super::FitFailureReason;
+use super::shape::Region;
+
+/// Constraints on the number of lines the text is set in, on top of fitting the box
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FitMode {
+	/// any number of lines, as long as they fit in the box
+	FillBox,
+	/// all the text on one line
+	SingleLine,
+	/// no more than this many lines
+	MaxLines(u32),
+	/// at least this many lines
+	MinLines(u32),
+}
+
+impl Default for FitMode {
+	fn default() -> Self {
+		FitMode::FillBox
+	}
+}
+
+/// How text is broken into lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WrapStyle {
+	/// as many words on each line as will fit
+	Greedy,
+	/// the same number of lines as greedy wrapping, but as narrow as possible,
+	/// so that the lines are of similar lengths
+	Balanced,
+}
+
+impl Default for WrapStyle {
+	fn default() -> Self {
+		WrapStyle::Greedy
+	}
+}
+
+/// Extra space between letters, either fixed or in proportion to the font size
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LetterSpacing {
+	/// in `points * pango::SCALE`
+	Fixed(i32),
+	/// as a multiple of the font size
+	Em(f64),
+}
+
+impl Default for LetterSpacing {
+	fn default() -> Self {
+		LetterSpacing::Fixed(0)
+	}
+}
+
+impl Hash for LetterSpacing {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		match self {
+			LetterSpacing::Fixed(n) => n.hash(state),
+			LetterSpacing::Em(e) => e.to_bits().hash(state),
+		}
+	}
+}
+
+impl LetterSpacing {
+
+	/// The spacing at `font_size`; both are in `points * pango::SCALE`
+	pub fn at(self, font_size: i32) -> i32 {
+		match self {
+			LetterSpacing::Fixed(n) => n,
+			LetterSpacing::Em(e) => (e * f64::from(font_size)).round() as i32,
+		}
+	}
+}
+
+/// Everything about the lines of text which decides whether a font size works
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct LineRules<'a> {
+	pub(crate) mode: FitMode,
+	/// reject sizes which leave widows or orphans; not checked for text in a shape
+	pub(crate) avoid_stranded_lines: bool,
+	/// the shape and exclusions every line must fit between, if the box is not simply a rectangle
+	pub(crate) region: Option<Region<'a>>,
+	/// the letter spacing to set along with each font size
+	pub(crate) letter_spacing: LetterSpacing,
+	/// the space after each paragraph, as a multiple of the line height
+	pub(crate) paragraph_spacing: f64,
+}
+
+impl From<FitMode> for LineRules<'_> {
+	fn from(mode: FitMode) -> Self {
+		LineRules {
+			mode,
+			avoid_stranded_lines: false,
+			region: None,
+			letter_spacing: LetterSpacing::default(),
+			paragraph_spacing: 0.0
+		}
+	}
+}
+
+impl FitMode {
+
+	/// Why `line_count` lines is not allowed, if it is not
+	pub(crate) fn check(self, line_count: i32) -> Option<FitFailureReason> {
+		let line_count = line_count as u32;
+		match self {
+			FitMode::FillBox => None,
+			FitMode::SingleLine if line_count > 1 => Some(FitFailureReason::TooManyLines),
+			FitMode::MaxLines(n) if line_count > n => Some(FitFailureReason::TooManyLines),
+			FitMode::MinLines(n) if line_count < n => Some(FitFailureReason::TooFewLines),
+			_ => None
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::layout::LayoutExtension;
+	use crate::layout::tests::create_layout_for_testing;
+	use pango::SCALE;
+
+	#[test]
+	fn layout_fit_modes() {
+		let l = create_layout_for_testing();
+		let sizes = (10..100).map(|i| i * SCALE).collect::<Vec<i32>>();
+		let single = l.try_font_sizes(&sizes, FitMode::SingleLine.into()).unwrap();
+		assert_eq!(l.get_line_count(), 1);
+		assert_eq!(single.smallest_failure.clone().unwrap().reason, FitFailureReason::TooManyLines);
+		// a single line only has to fit the width, however little height there is
+		let height = l.get_height();
+		l.set_height(SCALE);
+		let low = l.try_font_sizes(&sizes, FitMode::SingleLine.into()).unwrap();
+		assert_eq!(low.largest_fit, single.largest_fit);
+		assert_eq!(l.get_height(), SCALE);
+		l.set_height(height);
+		let boxed = l.try_font_sizes(&sizes, LineRules::default()).unwrap();
+		assert!(single.largest_fit.unwrap() <= boxed.largest_fit.unwrap());
+		let lines = l.get_line_count() as u32;
+
+		let at_least = l.try_font_sizes(&sizes, FitMode::MinLines(lines).into()).unwrap();
+		assert_eq!(at_least.largest_fit, boxed.largest_fit);
+		let at_most = l.try_font_sizes(&sizes, FitMode::MaxLines(lines).into()).unwrap();
+		assert_eq!(at_most.largest_fit, boxed.largest_fit);
+		let too_many = l.try_font_sizes(&sizes, FitMode::MinLines(lines + 1).into()).unwrap();
+		assert!(!too_many.fits());
+		assert_eq!(too_many.smallest_failure.unwrap().reason, FitFailureReason::TooFewLines);
+	}
+}
@@ -0,0 +1,336 @@
+use crate::errors::SvgTextBoxError;
+use glib::translate::ToGlibPtr;
+use pango::{AttrIterator, AttrList, AttrType, Attribute, FontDescription, Gravity, LayoutIter, Style, Weight, SCALE};
+use serde::{Deserialize, Serialize};
+use super::interface::FittedLayout;
+use crate::hyphenation::SOFT_HYPHEN;
+use super::shape::OBJECT_REPLACEMENT;
+
+/// How text is written in svg output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SvgTextMode {
+	/// each glyph is drawn as a path by cairo
+	Outlines,
+	/// text is written as `<text>` elements, so it can be searched, selected and read aloud.
+	/// Vertical writing, justified text and letter spacing can't be written this way,
+	/// and fail with `ConflictingAttributes`.
+	LiveText,
+}
+
+impl Default for SvgTextMode {
+	fn default() -> Self {
+		SvgTextMode::Outlines
+	}
+}
+
+/// A single run of text sharing the same attributes
+struct Run {
+	start: usize,
+	x: i32,
+	baseline: i32,
+	/// the byte index at which the first cluster of glyphs in the run ends
+	first_cluster_end: usize,
+}
+
+/// Write `fitted` as an svg document using `<text>` and `<tspan>` elements,
+/// inside a `<textPath>` if the text follows a path. Ids begin with `id_prefix`.
+///
+/// A tspan only gives where its run starts, and svg text is not turned upright in
+/// vertical lines, so vertical, justified and letter-spaced text can't be written
+/// this way; these fail with `ConflictingAttributes` rather than being misplaced.
+pub(crate) fn to_live_text_svg(fitted: &FittedLayout, id_prefix: &str) -> Result<String, SvgTextBoxError> {
+	let layout = &fitted.layout;
+	// vertical lines are laid out on their side with pango turning glyphs of vertical
+	// scripts back upright, which svg text would not do, leaving them lying down
+	let vertical = layout.get_context()
+		.map(|c| c.get_base_gravity() == Gravity::East)
+		.unwrap_or(false);
+	if vertical {
+		return Err(SvgTextBoxError::ConflictingAttributes("live text can't be written vertically".to_string()));
+	}
+	let attrs = layout.get_attributes();
+	// stretched spaces and spaced letters move each glyph along, but a tspan
+	// only gives the position of the start of its run
+	if layout.get_justify() || attrs.as_ref().map(has_letter_spacing).unwrap_or(false) {
+		return Err(SvgTextBoxError::ConflictingAttributes("live text can't be justified or letter spaced".to_string()));
+	}
+	let text = layout.get_text()
+		.ok_or(SvgTextBoxError::UnexpectedNone)?
+		.to_string();
+	let base_desc = layout.get_font_description()
+		.unwrap_or_default();
+
+	// runs are gathered first, since a run only ends where the next begins;
+	// there is also an empty run at the end of every line
+	let mut runs = Vec::new();
+	let ellipsized = layout.is_ellipsized();
+	let mut iter = layout.get_iter()
+		.ok_or(SvgTextBoxError::UnexpectedNone)?;
+	let mut line = 0;
+	loop {
+		let start = iter.get_index() as usize;
+		let run = if iter.get_run().is_some() {
+			let (_ink_extents, logical_extents) = iter.get_run_extents();
+			// lines after a paragraph break are moved down by the space between paragraphs
+			let paragraph_offset = fitted.paragraph_offsets.get(line).cloned().unwrap_or(0);
+			Some(Run {
+				start,
+				x: logical_extents.x,
+				baseline: iter.get_baseline() + paragraph_offset,
+				first_cluster_end: first_cluster_end(&iter),
+			})
+		} else {
+			line += 1;
+			None
+		};
+		runs.push((start, run));
+		if !iter.next_run() {
+			break;
+		}
+	}
+
+	let mut tspans = Vec::new();
+	let mut start_offset = None;
+	for (i, (_start, run)) in runs.iter().enumerate() {
+		let run = match run {
+			Some(r) => r,
+			None => continue
+		};
+		let end = runs.get(i + 1)
+			.map(|(next_start, _)| *next_start)
+			.unwrap_or_else(|| text.len());
+		let whole_run = text.get(run.start..end)
+			.unwrap_or("");
+		let run_text = whole_run
+			.trim_end_matches(|c: char| c == '\n' || c == '\r' || c == '\u{2028}' || c == '\u{2029}');
+		let line_end = runs.get(i + 1)
+			.map(|(_, next)| next.is_none())
+			.unwrap_or(true);
+		let run_text = if ellipsized && is_ellipsis(run, whole_run, end) {
+			"\u{2026}".to_string()
+		} else {
+			visible_text(run_text, line_end)
+		};
+		if run_text.is_empty() {
+			continue;
+		}
+		let mut desc = base_desc.clone();
+		let mut fill = None;
+		if let Some(mut attr_iter) = attrs.as_ref().and_then(|a| attr_iterator_at(a, run.start as i32)) {
+			attr_iter.get_font(&mut desc, None, &[]);
+			fill = foreground(&mut attr_iter);
+		}
+		// runs on a path follow on from each other along it
+		let position = match fitted.path {
+			Some(_) => String::new(),
+			None => format!(
+				"x=\"{}\" y=\"{}\" ",
+				fitted.x + f64::from(run.x) / f64::from(SCALE),
+				fitted.y + f64::from(run.baseline) / f64::from(SCALE)
+			)
+		};
+		if start_offset.is_none() {
+			start_offset = Some(f64::from(run.x) / f64::from(SCALE));
+		}
+		tspans.push(format!(
+			"<tspan {}{}{}>{}</tspan>",
+			position,
+			font_attributes(&desc),
+			fill.map(|f| format!(" fill=\"{}\"", f)).unwrap_or_default(),
+			escape(&run_text)
+		));
+	}
+	let (clip_path, clip) = match fitted.clip {
+		Some((x, y, width, height)) => (
+			format!("<clipPath id=\"{}clip\">\n<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"/>\n</clipPath>\n", id_prefix, x, y, width, height),
+			format!(" clip-path=\"url(#{}clip)\"", id_prefix)
+		),
+		None => (String::new(), String::new())
+	};
+	let (text_path, tspans) = match &fitted.path {
+		Some(path) => (
+			format!("<path id=\"{}text-path\" d=\"{}\" transform=\"translate({} {})\"/>\n", id_prefix, path.d(), fitted.x, fitted.y),
+			format!(
+				"<textPath href=\"#{}text-path\" startOffset=\"{}\">\n{}\n</textPath>",
+				id_prefix,
+				start_offset.unwrap_or(0.0),
+				tspans.join("\n")
+			)
+		),
+		None => (String::new(), tspans.join("\n"))
+	};
+	let svg = format!(
+		"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+		<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}pt\" height=\"{h}pt\" viewBox=\"0 0 {w} {h}\" version=\"1.1\">\n\
+		<defs>\n{clip_path}{text_path}</defs>\n\
+		<text xml:space=\"preserve\" fill=\"black\"{clip}{transform}>\n{tspans}\n</text>\n\
+		</svg>\n",
+		w = fitted.width,
+		h = fitted.height,
+		clip_path = clip_path,
+		clip = clip,
+		transform = fitted.orientation.svg_transform(fitted.upright_size.0, fitted.upright_size.1),
+		text_path = text_path,
+		tspans = tspans
+	);
+	Ok(svg)
+}
+
+/// Where the first cluster of the run at `iter` ends, or `usize::MAX` if it ends the layout
+fn first_cluster_end(iter: &LayoutIter) -> usize {
+	let mut cluster = iter.clone();
+	if cluster.next_cluster() {
+		cluster.get_index() as usize
+	} else {
+		usize::MAX
+	}
+}
+
+/// Whether `run`, which holds `run_text` and ends at `end`, is the ellipsis pango put in
+/// place of hidden text. It covers all the hidden characters with a single cluster.
+fn is_ellipsis(run: &Run, run_text: &str, end: usize) -> bool {
+	run.first_cluster_end >= end && run_text.chars().nth(1).is_some()
+}
+
+/// An attribute iterator positioned at the range containing `index`
+fn attr_iterator_at(attrs: &AttrList, index: i32) -> Option<AttrIterator> {
+	let mut iter = attrs.get_iterator()?;
+	loop {
+		let (start, end) = iter.range();
+		if index >= start && index < end {
+			return Some(iter);
+		}
+		if !iter.next() {
+			return None;
+		}
+	}
+}
+
+/// Whether any of `attrs` spaces letters apart
+fn has_letter_spacing(attrs: &AttrList) -> bool {
+	let unspaced = match Attribute::new_letter_spacing(0) {
+		Some(a) => a,
+		None => return false
+	};
+	let class = unspaced.get_attr_class();
+	let mut spaced = false;
+	attrs.filter(|a| {
+		// attributes are equal when their values are, whatever their ranges
+		spaced = spaced || (a.get_attr_class() == class && *a != unspaced);
+		false
+	});
+	spaced
+}
+
+/// The foreground colour set at the current position of `iter`, as a hex colour
+fn foreground(iter: &mut AttrIterator) -> Option<String> {
+	let attr = iter.get(AttrType::Foreground)?;
+	// pango 0.7 has no getter for colour values, so it is read from the struct pango-sys
+	// declares for attributes of this type, which is all `get` returns for it
+	let color = unsafe {
+		let ptr: *const pango_sys::PangoAttribute = attr.to_glib_none().0;
+		(*(ptr as *const pango_sys::PangoAttrColor)).color
+	};
+	Some(format!("#{:02x}{:02x}{:02x}", color.red >> 8, color.green >> 8, color.blue >> 8))
+}
+
+fn font_attributes(desc: &FontDescription) -> String {
+	let mut attributes = Vec::new();
+	if let Some(family) = desc.get_family() {
+		attributes.push(format!("font-family=\"{}\"", escape(&family)));
+	}
+	if desc.get_size() > 0 {
+		let size = f64::from(desc.get_size()) / f64::from(SCALE);
+		attributes.push(format!("font-size=\"{}\"", size));
+	}
+	let weight = match desc.get_weight() {
+		Weight::Thin => 100,
+		Weight::Ultralight => 200,
+		Weight::Light => 300,
+		Weight::Semilight => 350,
+		Weight::Book => 380,
+		Weight::Normal => 400,
+		Weight::Medium => 500,
+		Weight::Semibold => 600,
+		Weight::Bold => 700,
+		Weight::Ultrabold => 800,
+		Weight::Heavy => 900,
+		Weight::Ultraheavy => 1000,
+		Weight::__Unknown(w) => w,
+	};
+	attributes.push(format!("font-weight=\"{}\"", weight));
+	let style = match desc.get_style() {
+		Style::Italic => "italic",
+		Style::Oblique => "oblique",
+		_ => "normal"
+	};
+	attributes.push(format!("font-style=\"{}\"", style));
+	attributes.join(" ")
+}
+
+/// Soft hyphens are only drawn, as a hyphen, where pango broke the line at one.
+/// The placeholders which indent text set in a shape are never drawn.
+fn visible_text(run_text: &str, line_end: bool) -> String {
+	let broken = line_end && run_text.ends_with(SOFT_HYPHEN);
+	let mut visible = run_text.replace(SOFT_HYPHEN, "")
+		.replace(OBJECT_REPLACEMENT, "");
+	if broken {
+		visible.push('-');
+	}
+	visible
+}
+
+fn escape(s: &str) -> String {
+	s.replace('&', "&amp;")
+		.replace('<', "&lt;")
+		.replace('>', "&gt;")
+		.replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::layout::tests::TestSource;
+	use crate::layout::{RenderedTextbox, Justification};
+
+	#[test]
+	fn rendered_live_text() {
+		let src = TestSource::new(200, 100)
+			.with_markup("Fish &amp; <b>Chips</b> <span foreground=\"red\">&lt;3</span>")
+			.with_text_mode(SvgTextMode::LiveText);
+		let rendered = RenderedTextbox::new(&src).unwrap();
+		assert!(rendered.src.contains("<text"));
+		assert!(rendered.src.contains("Fish &amp; "));
+		assert!(rendered.src.contains("font-weight=\"700\""));
+		assert!(rendered.src.contains(">Chips</tspan>"));
+		assert!(rendered.src.contains("fill=\"#ff0000\""));
+		assert!(rendered.src.contains("&lt;3"));
+		assert!(rendered.src.contains("</defs>"));
+
+		let outlines = RenderedTextbox::with_text_mode(&src, SvgTextMode::Outlines).unwrap();
+		assert!(!outlines.src.contains("<tspan"));
+
+		// the tspans could not place glyphs moved apart by justification or letter spacing
+		let justified = TestSource::new(200, 100)
+			.with_markup("Fish &amp; Chips")
+			.with_text_mode(SvgTextMode::LiveText)
+			.with_justification(Justification::JustifyAllLines);
+		assert!(RenderedTextbox::with_text_mode(&justified, SvgTextMode::Outlines).is_ok());
+		match RenderedTextbox::new(&justified) {
+			Err(SvgTextBoxError::ConflictingAttributes(_)) => (),
+			_ => panic!("expected justified live text to be refused")
+		}
+		let spaced = TestSource::new(200, 100)
+			.with_markup("Fish &amp; <span letter_spacing=\"1024\">Chips</span>")
+			.with_text_mode(SvgTextMode::LiveText);
+		match RenderedTextbox::new(&spaced) {
+			Err(SvgTextBoxError::ConflictingAttributes(_)) => (),
+			_ => panic!("expected letter spaced live text to be refused")
+		}
+		let unspaced = TestSource::new(200, 100)
+			.with_markup("Fish &amp; <span letter_spacing=\"0\">Chips</span>")
+			.with_text_mode(SvgTextMode::LiveText);
+		assert!(RenderedTextbox::new(&unspaced).is_ok());
+	}
+}
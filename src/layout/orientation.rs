@@ -0,0 +1,170 @@
+use pango::Direction;
+use serde::{Deserialize, Serialize};
+
+/// Which way lines of text run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WritingMode {
+	/// lines run left to right, one below another
+	#[serde(alias = "horizontal-tb")]
+	Horizontal,
+	/// lines run top to bottom, starting from the right, as in Chinese and Japanese.
+	/// Glyphs of those scripts stay upright, while others are turned to lie along the lines.
+	#[serde(alias = "vertical-rl")]
+	Vertical,
+}
+
+impl Default for WritingMode {
+	fn default() -> Self {
+		WritingMode::Horizontal
+	}
+}
+
+/// The direction text runs in along each line, which decides the side it starts from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TextDirection {
+	/// left to right, as in English
+	Ltr,
+	/// right to left, as in Arabic and Hebrew
+	Rtl,
+	/// the direction of the first character which has one, or left to right if none do.
+	/// Paragraphs which start in the other direction are set in that direction.
+	Auto,
+}
+
+impl Default for TextDirection {
+	fn default() -> Self {
+		TextDirection::Auto
+	}
+}
+
+impl TextDirection {
+
+	/// Whether text set from `markup` runs right to left
+	pub fn is_rtl(self, markup: &str) -> bool {
+		match self {
+			TextDirection::Ltr => false,
+			TextDirection::Rtl => true,
+			TextDirection::Auto => pango::parse_markup(markup, '\u{0}')
+				.map(|(_attrs, text, _accel)| pango::find_base_dir(&text) == Direction::Rtl)
+				.unwrap_or(false)
+		}
+	}
+}
+
+/// How far the text is turned on the page, clockwise
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Orientation {
+	/// from 0 up to 360
+	degrees: f64,
+}
+
+impl Default for Orientation {
+	fn default() -> Self {
+		Orientation {
+			degrees: 0.0
+		}
+	}
+}
+
+impl Orientation {
+
+	/// Turned by `rotation` degrees clockwise, and a quarter turn more for vertical text,
+	/// which is laid out as horizontal lines on their side
+	pub(crate) fn new(rotation: f64, mode: WritingMode) -> Self {
+		let extra = match mode {
+			WritingMode::Horizontal => 0.0,
+			WritingMode::Vertical => 90.0
+		};
+		Orientation {
+			degrees: (rotation + extra).rem_euclid(360.0)
+		}
+	}
+
+	pub(crate) fn is_upright(&self) -> bool {
+		self.degrees == 0.0
+	}
+
+	/// Whether the width and height of the box change places
+	fn is_sideways(&self) -> bool {
+		self.degrees == 90.0 || self.degrees == 270.0
+	}
+
+	/// The (width, height) to lay the text out in, for a box of `width` by `height`.
+	/// On their side, the width and height describe the box on the page, so they are swapped;
+	/// at any other angle they describe the box along the text.
+	pub(crate) fn layout_size(&self, width: i32, height: i32) -> (i32, i32) {
+		if self.is_sideways() {
+			(height, width)
+		} else {
+			(width, height)
+		}
+	}
+
+	/// The width and height of the smallest upright box around a box `width` by `height` once it is turned
+	pub(crate) fn bounding_size(&self, width: f64, height: f64) -> (f64, f64) {
+		if self.is_sideways() {
+			return (height, width);
+		}
+		if self.degrees % 180.0 == 0.0 {
+			return (width, height);
+		}
+		let radians = self.degrees.to_radians();
+		let (sin, cos) = (radians.sin().abs(), radians.cos().abs());
+		(width * cos + height * sin, width * sin + height * cos)
+	}
+
+	/// Turn `context` about the middle of a box `width` by `height`,
+	/// so that the box fills its bounding box with the top left at the origin
+	pub(crate) fn apply(&self, context: &cairo::Context, width: f64, height: f64) {
+		if self.is_upright() {
+			return;
+		}
+		let (bounding_width, bounding_height) = self.bounding_size(width, height);
+		context.translate(bounding_width / 2.0, bounding_height / 2.0);
+		context.rotate(self.degrees.to_radians());
+		context.translate(-width / 2.0, -height / 2.0);
+	}
+
+	/// The same turn as `apply`, as an svg `transform` attribute
+	pub(crate) fn svg_transform(&self, width: f64, height: f64) -> String {
+		if self.is_upright() {
+			return String::new();
+		}
+		let (bounding_width, bounding_height) = self.bounding_size(width, height);
+		format!(
+			" transform=\"translate({} {}) rotate({}) translate({} {})\"",
+			bounding_width / 2.0,
+			bounding_height / 2.0,
+			self.degrees,
+			-width / 2.0,
+			-height / 2.0
+		)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn orientation_sizes() {
+		let spine = Orientation::new(90.0, WritingMode::Horizontal);
+		assert_eq!(spine.layout_size(30, 200), (200, 30));
+		assert_eq!(spine.bounding_size(200.0, 30.0), (30.0, 200.0));
+		let vertical = Orientation::new(0.0, WritingMode::Vertical);
+		assert_eq!(vertical, spine);
+		assert_eq!(Orientation::new(-90.0, WritingMode::Horizontal), Orientation::new(270.0, WritingMode::Horizontal));
+		assert!(Orientation::new(360.0, WritingMode::Horizontal).is_upright());
+		let upside_down = Orientation::new(180.0, WritingMode::Horizontal);
+		assert_eq!(upside_down.layout_size(30, 200), (30, 200));
+		assert_eq!(upside_down.bounding_size(30.0, 200.0), (30.0, 200.0));
+		// at other angles the width and height are those of the text
+		let tilted = Orientation::new(45.0, WritingMode::Horizontal);
+		assert_eq!(tilted.layout_size(100, 100), (100, 100));
+		let (width, height) = tilted.bounding_size(100.0, 100.0);
+		assert!((width - 200.0 / 2f64.sqrt()).abs() < 1e-9);
+		assert!((height - width).abs() < 1e-9);
+	}
+}
@@ -0,0 +1,36 @@
+use serde::{Deserialize, Serialize};
+use pango::EllipsizeMode;
+
+/// What to do when the text does not fit at any font size
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverflowPolicy {
+	/// fail with the fit report
+	Error,
+	/// replace text at the start with an ellipsis, at the smallest font size
+	EllipsizeStart,
+	/// replace text in the middle with an ellipsis, at the smallest font size
+	EllipsizeMiddle,
+	/// replace text at the end with an ellipsis, at the smallest font size
+	EllipsizeEnd,
+	/// cut off whatever falls outside the box, at the smallest font size
+	Clip,
+}
+
+impl Default for OverflowPolicy {
+	fn default() -> Self {
+		OverflowPolicy::Error
+	}
+}
+
+impl OverflowPolicy {
+
+	pub(crate) fn ellipsize_mode(self) -> EllipsizeMode {
+		match self {
+			OverflowPolicy::EllipsizeStart => EllipsizeMode::Start,
+			OverflowPolicy::EllipsizeMiddle => EllipsizeMode::Middle,
+			OverflowPolicy::EllipsizeEnd => EllipsizeMode::End,
+			OverflowPolicy::Error | OverflowPolicy::Clip => EllipsizeMode::None,
+		}
+	}
+}
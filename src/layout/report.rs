@@ -0,0 +1,97 @@
+use serde::Serialize;
+use super::OverflowPolicy;
+
+/// Why a layout did not fit at a particular font size
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FitFailureReason {
+	/// Pango ellipsized the text
+	Ellipsized,
+	/// Pango silently dropped characters off the end of the layout
+	DroppedChars,
+	/// the text was set in more lines than the fit mode allows
+	TooManyLines,
+	/// a line of text was wider than the box
+	TooWide,
+	/// the text was set in fewer lines than the fit mode requires
+	TooFewLines,
+	/// a paragraph ended with a single word on its last line
+	Widow,
+	/// a paragraph started with a single word on its first line
+	Orphan,
+	/// the text reached the bottom of its shape before it was all set
+	OutsideShape,
+	/// the lines were higher than the box once the paragraphs were spaced apart
+	TooHigh,
+}
+
+/// A font size which did not fit, and by how much it overflowed.
+/// All units are `points * pango::SCALE`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FitFailure {
+    pub font_size: i32,
+    pub reason: FitFailureReason,
+    /// how far the unconstrained text extends beyond the layout width
+    pub overflow_width: i32,
+    /// how far the unconstrained text extends beyond the layout height
+    pub overflow_height: i32,
+}
+
+/// The result of trying every font size against a single (width, height) candidate.
+/// All units are `points * pango::SCALE`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DimensionAttempt {
+    pub width: i32,
+    pub height: i32,
+    /// the largest font size which fit, if any did
+    pub largest_fit: Option<i32>,
+    /// the smallest font size which did not fit, if any failed
+    pub smallest_failure: Option<FitFailure>,
+}
+
+impl DimensionAttempt {
+	/// Whether any font size fit these dimensions
+	pub fn fits(&self) -> bool {
+		self.largest_fit.is_some()
+	}
+}
+
+/// How the text was cut short when no font size fit.
+/// All units are `points * pango::SCALE`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Truncation {
+    pub policy: OverflowPolicy,
+    /// the font size used, which is the smallest possible
+    pub font_size: i32,
+}
+
+/// A record of every candidate tried while looking for the best fit
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct FitReport {
+    /// each (width, height) candidate, in the order tried
+    pub attempts: Vec<DimensionAttempt>,
+    /// the index in `attempts` of the candidate finally used
+    pub chosen: Option<usize>,
+    /// set if nothing fit, and the text was truncated rather than failing
+    pub truncation: Option<Truncation>,
+    /// whether the fit was found in a `FitCache`, in which case only the chosen
+    /// candidate is reported
+    pub cached: bool,
+    /// whether the lines were set one by one in a shape or around exclusions,
+    /// which also placed them vertically
+    pub flowed: bool,
+}
+
+impl FitReport {
+	/// The attempt finally used, if there was one
+	pub fn chosen(&self) -> Option<&DimensionAttempt> {
+		self.chosen.and_then(|i| self.attempts.get(i))
+	}
+
+	/// The font size finally used, whether or not the text was truncated
+	pub fn font_size(&self) -> Option<i32> {
+		self.chosen()
+			.and_then(|a| a.largest_fit)
+			.or_else(|| self.truncation.as_ref().map(|t| t.font_size))
+	}
+}
@@ -0,0 +1,613 @@
+use crate::errors::SvgTextBoxError;
+use crate::hyphenation::SOFT_HYPHEN;
+use glib::translate::{from_glib_full, ToGlibPtr};
+use kurbo::{BezPath, PathEl};
+use pango::{Alignment, AttrList, Attribute, Direction, EllipsizeMode, Layout, Rectangle, SCALE};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use super::VerticalAlignment;
+use super::chain::{set_sub_text, sub_layout};
+use super::{get_line_spacing, set_line_spacing, ends_paragraph, is_paragraph_break, paragraph_gap};
+
+/// Stands in for the space before each line of text set in a shape
+pub(crate) const OBJECT_REPLACEMENT: char = '\u{fffc}';
+
+/// How closely curves are followed when a path becomes a polygon, in points
+pub(super) const PATH_TOLERANCE: f64 = 0.1;
+
+/// The outline text is set inside, within the width and height of its box
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Shape {
+	/// the whole box
+	Rectangle,
+	/// the ellipse which touches every side of the box
+	Ellipse,
+	/// the largest circle in the middle of the box
+	Circle,
+	/// the polygon with these (x, y) corners, in points from the top left of the box
+	Polygon(Vec<(f64, f64)>),
+}
+
+impl Default for Shape {
+	fn default() -> Self {
+		Shape::Rectangle
+	}
+}
+
+impl Shape {
+
+	/// A polygon following the first subpath of the svg path data `d`,
+	/// for a box whose top left is at (x, y) in the path's coordinates
+	pub fn from_svg_path(d: &str, x: f64, y: f64) -> Result<Shape, SvgTextBoxError> {
+		Ok(Shape::Polygon(path_points(d, x, y)?))
+	}
+
+	/// The part of the band from `top` to `bottom` which is inside this shape all the way down,
+	/// for a box `width` by `height`, as (left, right).
+	/// All units are `points * pango::SCALE`.
+	pub(crate) fn span(&self, width: i32, height: i32, top: i32, bottom: i32) -> Option<(i32, i32)> {
+		if top < 0 || bottom > height {
+			return None;
+		}
+		let (w, h) = (f64::from(width), f64::from(height));
+		let (top, bottom) = (f64::from(top), f64::from(bottom));
+		let (left, right) = match self {
+			Shape::Rectangle => (0.0, w),
+			Shape::Ellipse => ellipse_span(w / 2.0, h / 2.0, w / 2.0, h / 2.0, top, bottom)?,
+			Shape::Circle => {
+				let r = w.min(h) / 2.0;
+				ellipse_span(w / 2.0, h / 2.0, r, r, top, bottom)?
+			},
+			Shape::Polygon(points) => polygon_span(&scaled(points), top, bottom)?
+		};
+		let (left, right) = (left.max(0.0).ceil() as i32, right.min(w).floor() as i32);
+		if right > left {
+			Some((left, right))
+		} else {
+			None
+		}
+	}
+}
+
+/// An area inside the box which text flows around, in points from the top left of the box
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Exclusion {
+	Rectangle {
+		x: f64,
+		y: f64,
+		width: f64,
+		height: f64,
+	},
+	Ellipse {
+		cx: f64,
+		cy: f64,
+		rx: f64,
+		ry: f64,
+	},
+	/// the polygon with these (x, y) corners
+	Polygon(Vec<(f64, f64)>),
+}
+
+impl Exclusion {
+
+	/// A polygon following the first subpath of the svg path data `d`,
+	/// for a box whose top left is at (x, y) in the path's coordinates
+	pub fn from_svg_path(d: &str, x: f64, y: f64) -> Result<Exclusion, SvgTextBoxError> {
+		Ok(Exclusion::Polygon(path_points(d, x, y)?))
+	}
+
+	/// How far across this reaches anywhere in the band from `top` to `bottom`,
+	/// as (left, right). All units are `points * pango::SCALE`.
+	fn extent(&self, top: f64, bottom: f64) -> Option<(f64, f64)> {
+		let scale = f64::from(SCALE);
+		match self {
+			Exclusion::Rectangle {x, y, width, height} => {
+				let (rect_top, rect_bottom) = (y * scale, (y + height) * scale);
+				if rect_bottom <= top || rect_top >= bottom {
+					return None;
+				}
+				Some((x * scale, (x + width) * scale))
+			},
+			Exclusion::Ellipse {cx, cy, rx, ry} => {
+				let (cx, cy, rx, ry) = (cx * scale, cy * scale, rx * scale, ry * scale);
+				// the widest chord is at the edge of the band nearest the centre
+				let dy = if top <= cy && cy <= bottom {
+					0.0
+				} else {
+					(top - cy).abs().min((bottom - cy).abs())
+				};
+				if dy >= ry {
+					return None;
+				}
+				let half = rx * (1.0 - (dy / ry).powi(2)).sqrt();
+				Some((cx - half, cx + half))
+			},
+			Exclusion::Polygon(points) => {
+				let points = scaled(points);
+				// the corners within the band, and wherever the edges cross its top and bottom
+				let mut xs = points.iter()
+					.filter(|p| p.1 >= top && p.1 <= bottom)
+					.map(|p| p.0)
+					.collect::<Vec<f64>>();
+				for y in [top, bottom].iter() {
+					for (left, right) in polygon_intervals(&points, *y) {
+						xs.push(left);
+						xs.push(right);
+					}
+				}
+				let left = xs.iter().cloned().fold(f64::INFINITY, f64::min);
+				let right = xs.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+				if right > left {
+					Some((left, right))
+				} else {
+					None
+				}
+			}
+		}
+	}
+}
+
+/// A shape with exclusions taken out of it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Region<'a> {
+	pub(crate) shape: &'a Shape,
+	pub(crate) exclusions: &'a [Exclusion],
+}
+
+impl Region<'_> {
+
+	/// The widest part of the band from `top` to `bottom` which is inside the shape and
+	/// clear of every exclusion, as (left, right). Text never continues on the other side
+	/// of an exclusion. All units are `points * pango::SCALE`.
+	pub(crate) fn span(&self, width: i32, height: i32, top: i32, bottom: i32) -> Option<(i32, i32)> {
+		let mut free = vec![self.shape.span(width, height, top, bottom)?];
+		for exclusion in self.exclusions {
+			let (left, right) = match exclusion.extent(f64::from(top), f64::from(bottom)) {
+				Some((l, r)) => (l.floor() as i32, r.ceil() as i32),
+				None => continue
+			};
+			free = free.into_iter()
+				.flat_map(|(l, r)| vec![(l, r.min(left)), (l.max(right), r)])
+				.filter(|(l, r)| r > l)
+				.collect();
+		}
+		free.into_iter()
+			.max_by_key(|(l, r)| r - l)
+	}
+}
+
+/// The points of the first subpath of the svg path data `d`, with curves flattened,
+/// relative to (x, y)
+fn path_points(d: &str, x: f64, y: f64) -> Result<Vec<(f64, f64)>, SvgTextBoxError> {
+	let path = BezPath::from_svg(d)?;
+	let mut points = Vec::new();
+	let mut subpaths = 0;
+	path.flatten(PATH_TOLERANCE, |el| match el {
+		PathEl::MoveTo(p) => {
+			subpaths += 1;
+			if subpaths == 1 {
+				points.push((p.x - x, p.y - y));
+			}
+		},
+		PathEl::LineTo(p) if subpaths == 1 => points.push((p.x - x, p.y - y)),
+		_ => {}
+	});
+	if points.len() < 3 {
+		return Err(SvgTextBoxError::InvalidPath(d.to_string()));
+	}
+	Ok(points)
+}
+
+/// `points` in `points * pango::SCALE`
+fn scaled(points: &[(f64, f64)]) -> Vec<(f64, f64)> {
+	let scale = f64::from(SCALE);
+	points.iter()
+		.map(|(x, y)| (x * scale, y * scale))
+		.collect()
+}
+
+/// The narrowest chord of an ellipse across a band, which is at the edge furthest from its centre
+fn ellipse_span(cx: f64, cy: f64, rx: f64, ry: f64, top: f64, bottom: f64) -> Option<(f64, f64)> {
+	let dy = (top - cy).abs().max((bottom - cy).abs());
+	if dy >= ry {
+		return None;
+	}
+	let half = rx * (1.0 - (dy / ry).powi(2)).sqrt();
+	Some((cx - half, cx + half))
+}
+
+/// The widest interval inside the polygon at every height in the band. It is enough to check
+/// the top and bottom and each corner between, as the edges are straight.
+fn polygon_span(points: &[(f64, f64)], top: f64, bottom: f64) -> Option<(f64, f64)> {
+	// the bottom edge of the polygon only counts as inside just above it
+	let samples = points.iter()
+		.map(|p| p.1)
+		.filter(|y| *y > top && *y < bottom)
+		.chain(vec![top, (bottom - 1.0).max(top)]);
+	let mut inside: Option<Vec<(f64, f64)>> = None;
+	for y in samples {
+		let across = polygon_intervals(points, y);
+		inside = Some(match inside {
+			Some(i) => intersect(&i, &across),
+			None => across
+		});
+	}
+	inside?.into_iter()
+		.max_by(|a, b| (a.1 - a.0).partial_cmp(&(b.1 - b.0)).unwrap_or(Ordering::Equal))
+}
+
+/// The intervals along the line at `y` which are inside the polygon, by the even-odd rule
+fn polygon_intervals(points: &[(f64, f64)], y: f64) -> Vec<(f64, f64)> {
+	let mut crossings = Vec::new();
+	for (i, p) in points.iter().enumerate() {
+		let q = points[(i + 1) % points.len()];
+		if (p.1 <= y) != (q.1 <= y) {
+			crossings.push(p.0 + (y - p.1) / (q.1 - p.1) * (q.0 - p.0));
+		}
+	}
+	crossings.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+	crossings.chunks(2)
+		.filter(|c| c.len() == 2)
+		.map(|c| (c[0], c[1]))
+		.collect()
+}
+
+fn intersect(a: &[(f64, f64)], b: &[(f64, f64)]) -> Vec<(f64, f64)> {
+	a.iter()
+		.flat_map(|x| b.iter().map(move |y| (x.0.max(y.0), x.1.min(y.1))))
+		.filter(|(left, right)| right > left)
+		.collect()
+}
+
+/// A line of text set within a shape. All units are `points * pango::SCALE`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct FlowLine {
+	/// the byte indices of the text on this line
+	pub(crate) start: usize,
+	pub(crate) end: usize,
+	/// how far the text starts from the side of the box it starts at,
+	/// which is the right for right-to-left text
+	pub(crate) indent: i32,
+	/// the space between the top of this line and the bottom of the line above,
+	/// or the top of the box
+	pub(crate) gap: i32,
+	/// the distance from the top of the line to its baseline
+	pub(crate) ascent: i32,
+}
+
+/// Text broken into lines which each fit within a shape
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Flow {
+	pub(crate) lines: Vec<FlowLine>,
+	/// the bottom of the last line, in `points * pango::SCALE`
+	pub(crate) bottom: i32,
+}
+
+/// The first line of a layout, when the layout holds the rest of the text from `start`
+struct ProbedLine {
+	end: usize,
+	width: i32,
+	height: i32,
+	ascent: i32,
+}
+
+fn first_line(probe: &Layout, start: usize, text_end: usize) -> Option<ProbedLine> {
+	let mut iter = probe.get_iter()?;
+	let (_ink, logical) = iter.get_line_extents();
+	let ascent = iter.get_baseline() - logical.y;
+	let end = if iter.next_line() {
+		start + iter.get_index() as usize
+	} else {
+		text_end
+	};
+	Some(ProbedLine {
+		end,
+		width: logical.width,
+		height: logical.height,
+		ascent
+	})
+}
+
+/// The distance between the tops of lines as a multiple of their height. Lines set
+/// in a shape never overlap, so this is at least one.
+fn line_spacing(layout: &Layout) -> f64 {
+	get_line_spacing(layout).max(1.0)
+}
+
+/// Break the text of `layout` into lines which each fit within `region`, at the layout's
+/// size, font size and alignment, with the first line no higher than `top`.
+/// Wherever the region is too narrow for the next word, the text moves further down,
+/// and each paragraph is followed by `paragraph_spacing` lines of space.
+/// Returns None if the text reaches the bottom of the box before it is all set.
+pub(crate) fn flow(layout: &Layout, region: Region, top: i32, paragraph_spacing: f64) -> Option<Flow> {
+	let text = layout.get_text()?.to_string();
+	let attrs = layout.get_attributes();
+	let (width, height) = (layout.get_width(), layout.get_height());
+	let spacing = line_spacing(layout);
+	let gap = paragraph_gap(layout, paragraph_spacing);
+	let last_paragraph = text.trim_end_matches(is_paragraph_break).len();
+	let rtl = is_rtl(layout);
+	let mut lines = Vec::new();
+	let mut bottom = 0;
+	let mut y = top;
+	let mut start = 0;
+	// one copy of the layout is reused for each line, holding the text still to be set
+	let probe = sub_layout(layout, &text, attrs.as_ref(), start, text.len()).ok()?;
+	while start < text.len() {
+		if start > 0 {
+			set_sub_text(&probe, &text, attrs.as_ref(), start, text.len());
+		}
+		probe.set_width(width);
+		let mut line_height = first_line(&probe, start, text.len())?.height;
+		let (line, line_height) = loop {
+			if y + line_height > height {
+				return None;
+			}
+			let step = (line_height / 4).max(1);
+			let (left, right) = match region.span(width, height, y, y + line_height) {
+				Some(s) => s,
+				None => {
+					y += step;
+					continue;
+				}
+			};
+			probe.set_width(right - left);
+			let line = first_line(&probe, start, text.len())?;
+			if line.height > line_height {
+				// a taller line needs a band which is narrow for longer
+				line_height = line.height;
+				continue;
+			}
+			if line.width > right - left || line.end <= start {
+				y += step;
+				continue;
+			}
+			let spare = right - left - line.width;
+			let offset = left + match probe.get_alignment() {
+				Alignment::Center => spare / 2,
+				Alignment::Right => spare,
+				_ => 0
+			};
+			let indent = if rtl { width - offset - line.width } else { offset };
+			break (FlowLine {
+				start,
+				end: line.end,
+				indent,
+				gap: y - bottom,
+				ascent: line.ascent
+			}, line.height);
+		};
+		bottom = y + line_height;
+		y += (f64::from(line_height) * spacing).round() as i32;
+		if line.end < last_paragraph && ends_paragraph(&text[..line.end]) {
+			y += gap;
+		}
+		start = line.end;
+		lines.push(line);
+	}
+	Some(Flow {
+		lines,
+		bottom
+	})
+}
+
+/// Set the text of `layout` in `region`, moved as far towards where `alignment` puts it
+/// as the region allows, with `paragraph_spacing` lines of space after each paragraph.
+/// Returns whether the text was set; nothing changes if it does not fit.
+pub(crate) fn set_in_shape(layout: &Layout, region: Region, alignment: VerticalAlignment, paragraph_spacing: f64) -> bool {
+	let highest = match flow(layout, region, 0, paragraph_spacing) {
+		Some(f) => f,
+		None => return false
+	};
+	let first_top = highest.lines.first()
+		.map(|l| l.gap)
+		.unwrap_or(0);
+	let below = layout.get_height() - highest.bottom;
+	let target = match alignment {
+		VerticalAlignment::Top | VerticalAlignment::Baseline(_) => first_top,
+		VerticalAlignment::Bottom => first_top + below,
+		_ => first_top + below / 2
+	};
+	// the shape may narrow further down, so move back up until the text fits
+	let mut chosen = highest;
+	let mut top = target;
+	while top > first_top {
+		if let Some(f) = flow(layout, region, top, paragraph_spacing) {
+			chosen = f;
+			break;
+		}
+		top = first_top + (top - first_top) / 2;
+	}
+	apply_flow(layout, &chosen);
+	true
+}
+
+/// Replace the text of `layout` with the lines of `flow`, each after a placeholder as wide
+/// as its indent and as high as the gap above it, and each but the last ending in a break
+fn apply_flow(layout: &Layout, flow: &Flow) {
+	let text = match layout.get_text() {
+		Some(t) => t.to_string(),
+		None => return
+	};
+	let mut original = Vec::new();
+	if let Some(attrs) = layout.get_attributes() {
+		attrs.filter(|a| {
+			original.push(a.clone());
+			false
+		});
+	}
+	let mut flowed = String::with_capacity(text.len() + flow.lines.len() * 6);
+	let placed = AttrList::new();
+	for (i, line) in flow.lines.iter().enumerate() {
+		let line_text = &text[line.start..line.end];
+		// spaces where the line broke would only push it wider
+		let content = line_text.trim_end();
+		let hyphen = if content.ends_with(SOFT_HYPHEN) { "-" } else { "" };
+		let line_break = if ends_paragraph(line_text) {
+			"\n"
+		} else if i + 1 == flow.lines.len() {
+			""
+		} else {
+			"\u{2028}"
+		};
+		let placeholder_start = flowed.len() as u32;
+		flowed.push(OBJECT_REPLACEMENT);
+		if let Some(mut a) = placeholder(line.indent, line.gap + line.ascent) {
+			a.set_start_index(placeholder_start);
+			a.set_end_index(flowed.len() as u32);
+			placed.insert(a);
+		}
+		let content_start = flowed.len() as u32;
+		flowed.push_str(content);
+		flowed.push_str(hyphen);
+		flowed.push_str(line_break);
+		// attributes which reach the end of the text also cover what comes after it
+		let (line_start, line_end) = (line.start as u32, line.end as u32);
+		let content_end = content.len() as u32;
+		let tail_end = flowed.len() as u32 - content_start;
+		for a in original.iter() {
+			if a.get_end_index() <= line_start || a.get_start_index() >= line_end {
+				continue;
+			}
+			let s = (a.get_start_index().max(line_start) - line_start).min(content_end);
+			let e = a.get_end_index().min(line_end) - line_start;
+			let e = if e >= content_end { tail_end } else { e };
+			if e <= s {
+				continue;
+			}
+			let mut a = a.clone();
+			a.set_start_index(content_start + s);
+			a.set_end_index(content_start + e);
+			placed.insert(a);
+		}
+	}
+	layout.set_text(&flowed);
+	layout.set_attributes(Some(&placed));
+	// every line is already placed, so pango must not move or hide any of them
+	layout.set_alignment(if is_rtl(layout) { Alignment::Right } else { Alignment::Left });
+	layout.set_justify(false);
+	layout.set_ellipsize(EllipsizeMode::None);
+	set_line_spacing(layout, 0.0);
+}
+
+/// Whether lines of `layout` start from the right
+fn is_rtl(layout: &Layout) -> bool {
+	layout.get_context()
+		.map(|c| c.get_base_dir() == Direction::Rtl)
+		.unwrap_or(false)
+}
+
+/// An attribute which makes a character take up `width`, and `height` above the baseline,
+/// without drawing anything
+fn placeholder(width: i32, height: i32) -> Option<Attribute> {
+	let ink = Rectangle::new(0, 0, 0, 0);
+	let logical = Rectangle::new(0, -height, width, height);
+	unsafe {
+		from_glib_full(pango_sys::pango_attr_shape_new(ink.to_glib_none().0, logical.to_glib_none().0))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::layout::{LayoutExtension, LineRules, FitFailureReason};
+	use crate::layout::tests::create_layout_for_testing;
+	use pango::SCALE;
+
+	#[test]
+	fn shape_spans() {
+		let (w, h) = (200 * SCALE, 100 * SCALE);
+		assert_eq!(Shape::Rectangle.span(w, h, 0, 10 * SCALE), Some((0, w)));
+		assert_eq!(Shape::Rectangle.span(w, h, 95 * SCALE, 105 * SCALE), None);
+		// a band across the middle of an ellipse is nearly as wide as the box
+		let (left, right) = Shape::Ellipse.span(w, h, 45 * SCALE, 55 * SCALE).unwrap();
+		assert!(left > 0 && left < 20 * SCALE);
+		assert_eq!(left + right, w);
+		assert!(Shape::Ellipse.span(w, h, 0, 10 * SCALE).map(|(l, r)| r - l).unwrap_or(0) < right - left);
+		// the circle is only as wide as the box is high
+		let (left, right) = Shape::Circle.span(w, h, 45 * SCALE, 55 * SCALE).unwrap();
+		assert!(right - left <= h);
+		assert!(left >= 50 * SCALE);
+		// a triangle pointing down narrows towards the bottom
+		let triangle = Shape::Polygon(vec![(0.0, 0.0), (200.0, 0.0), (100.0, 100.0)]);
+		let (left, right) = triangle.span(w, h, 0, 50 * SCALE).unwrap();
+		assert!((left - 50 * SCALE).abs() <= 1 && (right - 150 * SCALE).abs() <= 1);
+		assert!(triangle.span(w, h, 99 * SCALE, 100 * SCALE).map(|(l, r)| r - l).unwrap_or(0) <= 2 * SCALE);
+	}
+
+	#[test]
+	fn layout_in_shape() {
+		let l = create_layout_for_testing();
+		l.set_markup("The quick brown fox jumps over the lazy dog");
+		let sizes = (10..60).map(|i| i * SCALE).collect::<Vec<i32>>();
+		let boxed = l.try_font_sizes(&sizes, LineRules::default()).unwrap();
+		let ellipse = Region {
+			shape: &Shape::Ellipse,
+			exclusions: &[]
+		};
+		let rules = LineRules {
+			region: Some(ellipse),
+			..LineRules::default()
+		};
+		let shaped = l.try_font_sizes(&sizes, rules).unwrap();
+		assert!(shaped.largest_fit.unwrap() < boxed.largest_fit.unwrap());
+		assert_eq!(shaped.smallest_failure.unwrap().reason, FitFailureReason::OutsideShape);
+
+		let flowed = flow(&l, ellipse, 0, 0.0).unwrap();
+		assert!(flowed.lines.len() > 1);
+		assert!(flowed.bottom <= l.get_height());
+		for line in flowed.lines.iter() {
+			assert!(line.indent > 0);
+		}
+		assert!(set_in_shape(&l, ellipse, VerticalAlignment::Top, 0.0));
+		let text = l.get_text().unwrap().to_string();
+		assert_eq!(l.get_line_count() as usize, flowed.lines.len());
+		assert_eq!(text.matches('\u{fffc}').count(), flowed.lines.len());
+		let words = text.replace(|c: char| c == '\u{fffc}' || c == '\u{2028}', " ");
+		assert_eq!(words.split_whitespace().collect::<Vec<&str>>().join(" "), "The quick brown fox jumps over the lazy dog");
+	}
+
+	#[test]
+	fn layout_around_exclusions() {
+		let (w, h) = (300 * SCALE, 150 * SCALE);
+		let logo = vec![Exclusion::Rectangle {x: 200.0, y: 0.0, width: 100.0, height: 50.0}];
+		let region = Region {
+			shape: &Shape::Rectangle,
+			exclusions: &logo
+		};
+		assert_eq!(region.span(w, h, 0, 20 * SCALE), Some((0, 200 * SCALE)));
+		assert_eq!(region.span(w, h, 60 * SCALE, 80 * SCALE), Some((0, w)));
+		// only the wider side of an obstacle in the middle is used
+		let post = vec![Exclusion::Ellipse {cx: 100.0, cy: 75.0, rx: 20.0, ry: 20.0}];
+		let around_post = Region {
+			shape: &Shape::Rectangle,
+			exclusions: &post
+		};
+		assert_eq!(around_post.span(w, h, 70 * SCALE, 80 * SCALE), Some((120 * SCALE, w)));
+
+		let l = create_layout_for_testing();
+		l.set_markup("The quick brown fox jumps over the lazy dog");
+		let sizes = (10..60).map(|i| i * SCALE).collect::<Vec<i32>>();
+		let boxed = l.try_font_sizes(&sizes, LineRules::default()).unwrap();
+		let rules = LineRules {
+			region: Some(region),
+			..LineRules::default()
+		};
+		let around = l.try_font_sizes(&sizes, rules).unwrap();
+		assert!(around.largest_fit.unwrap() <= boxed.largest_fit.unwrap());
+		set_in_shape(&l, region, VerticalAlignment::Top, 0.0);
+		// lines beside the logo stop short of it
+		let mut iter = l.get_iter().unwrap();
+		loop {
+			let (_ink, logical) = iter.get_line_extents();
+			if logical.y < 50 * SCALE {
+				assert!(logical.x + logical.width <= 200 * SCALE);
+			}
+			if !iter.next_line() {
+				break;
+			}
+		}
+	}
+}
@@ -0,0 +1,167 @@
+use super::DimensionAttempt;
+use crate::errors::SvgTextBoxError;
+
+/// Chooses which of the candidate (width, height) pairs to use.
+pub trait FitStrategy {
+	/// Whether to stop trying candidates once `attempt` has been made.
+	/// By default every candidate is tried.
+	fn accept_immediately(&self, _attempt: &DimensionAttempt) -> bool {
+		false
+	}
+	/// The index of the attempt to use, if any is acceptable
+	fn choose(&self, attempts: &[DimensionAttempt]) -> Option<usize>;
+	/// Identifies this strategy and its settings in a `FitCache`.
+	/// Fits chosen by a strategy without a key are never cached.
+	fn cache_key(&self) -> Option<String> {
+		None
+	}
+}
+
+/// Use the first candidate at which any font size fits
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FirstAcceptable;
+
+/// Use the candidate which allows the largest font size; the earliest wins ties
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LargestFontSize;
+
+/// Use the smallest candidate (by area) which reaches the given font size
+/// (in `points * pango::SCALE`). If none do, fall back to the largest font size.
+#[derive(Debug, Clone, Copy)]
+pub struct SmallestAreaAtFontSize(pub i32);
+
+/// Use the candidate whose width / height ratio is closest to the one given
+#[derive(Debug, Clone, Copy)]
+pub struct BestAspectRatio(pub f64);
+
+impl BestAspectRatio {
+	/// Fails unless the ratio is positive and finite
+	pub fn new(ratio: f64) -> Result<Self, SvgTextBoxError> {
+		if ratio > 0.0 && ratio.is_finite() {
+			Ok(BestAspectRatio(ratio))
+		} else {
+			Err(SvgTextBoxError::InvalidAspectRatio(ratio))
+		}
+	}
+}
+
+fn fitting<'a>(attempts: &'a [DimensionAttempt]) -> impl Iterator<Item=(usize, &'a DimensionAttempt)> + 'a {
+	attempts.iter()
+		.enumerate()
+		.filter(|(_, a)| a.fits())
+}
+
+/// The index of the fitting attempt with the smallest key; the earliest wins ties
+fn minimum_by<K, F>(attempts: &[DimensionAttempt], key: F) -> Option<usize>
+	where K: PartialOrd, F: Fn(&DimensionAttempt) -> K
+{
+	let mut best: Option<(usize, K)> = None;
+	for (i, a) in fitting(attempts) {
+		let k = key(a);
+		let better = match best {
+			Some((_, ref b)) => k < *b,
+			None => true
+		};
+		if better {
+			best = Some((i, k));
+		}
+	}
+	best.map(|(i, _)| i)
+}
+
+impl FitStrategy for FirstAcceptable {
+	fn cache_key(&self) -> Option<String> {
+		Some(format!("{:?}", self))
+	}
+
+	fn accept_immediately(&self, attempt: &DimensionAttempt) -> bool {
+		attempt.fits()
+	}
+
+	fn choose(&self, attempts: &[DimensionAttempt]) -> Option<usize> {
+		fitting(attempts)
+			.map(|(i, _)| i)
+			.next()
+	}
+}
+
+impl FitStrategy for LargestFontSize {
+	fn cache_key(&self) -> Option<String> {
+		Some(format!("{:?}", self))
+	}
+
+	fn choose(&self, attempts: &[DimensionAttempt]) -> Option<usize> {
+		minimum_by(attempts, |a| -a.largest_fit.unwrap_or(0))
+	}
+}
+
+impl FitStrategy for SmallestAreaAtFontSize {
+	fn cache_key(&self) -> Option<String> {
+		Some(format!("{:?}", self))
+	}
+
+	fn choose(&self, attempts: &[DimensionAttempt]) -> Option<usize> {
+		let SmallestAreaAtFontSize(target) = *self;
+		let reaches_target = attempts.iter()
+			.any(|a| a.largest_fit.map(|f| f >= target).unwrap_or(false));
+		if !reaches_target {
+			return LargestFontSize.choose(attempts);
+		}
+		minimum_by(attempts, |a| {
+			let area = i64::from(a.width) * i64::from(a.height);
+			match a.largest_fit {
+				Some(f) if f >= target => area,
+				_ => std::i64::MAX
+			}
+		})
+	}
+}
+
+impl FitStrategy for BestAspectRatio {
+	fn cache_key(&self) -> Option<String> {
+		Some(format!("{:?}", self))
+	}
+
+	fn choose(&self, attempts: &[DimensionAttempt]) -> Option<usize> {
+		let BestAspectRatio(ratio) = *self;
+		minimum_by(attempts, |a| {
+			let actual = f64::from(a.width) / f64::from(a.height);
+			(actual / ratio).ln().abs()
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn attempt(width: i32, height: i32, largest_fit: Option<i32>) -> DimensionAttempt {
+		DimensionAttempt {
+			width,
+			height,
+			largest_fit,
+			smallest_failure: None
+		}
+	}
+
+	#[test]
+	fn fit_strategies() {
+		let attempts = vec![
+			attempt(100, 100, None),
+			attempt(100, 200, Some(10)),
+			attempt(200, 100, Some(20)),
+			attempt(300, 300, Some(30)),
+			attempt(400, 400, Some(30)),
+		];
+		assert!(!FirstAcceptable.accept_immediately(&attempts[0]));
+		assert!(FirstAcceptable.accept_immediately(&attempts[1]));
+		assert_eq!(FirstAcceptable.choose(&attempts), Some(1));
+		assert_eq!(LargestFontSize.choose(&attempts), Some(3));
+		assert_eq!(SmallestAreaAtFontSize(15).choose(&attempts), Some(2));
+		assert_eq!(SmallestAreaAtFontSize(50).choose(&attempts), Some(3));
+		assert_eq!(BestAspectRatio(0.5).choose(&attempts), Some(1));
+		assert_eq!(BestAspectRatio(2.0).choose(&attempts), Some(2));
+		assert_eq!(BestAspectRatio(1.0).choose(&attempts), Some(3));
+		assert_eq!(LargestFontSize.choose(&attempts[..1]), None);
+	}
+}
@@ -0,0 +1,202 @@
+use crate::errors::SvgTextBoxError;
+use cairo::PathSegment;
+use kurbo::{Affine, BezPath, PathEl, Point};
+use pango::{Layout, SCALE};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use super::shape::PATH_TOLERANCE;
+
+/// Glyph outlines are cut into pieces no longer than this, in points, so that they bend with the path
+const BEND_STEP: f64 = 0.5;
+
+/// A path which a single line of text sits on, such as an arc round a seal.
+/// It is written as svg path data, in points from the top left of the box,
+/// and only its first subpath is followed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TextPath {
+	d: String,
+	/// the corners of the path with its curves flattened, as (x, y, distance from the start)
+	points: Vec<(f64, f64, f64)>,
+}
+
+impl TryFrom<String> for TextPath {
+	type Error = SvgTextBoxError;
+
+	fn try_from(d: String) -> Result<Self, Self::Error> {
+		TextPath::new(&d)
+	}
+}
+
+impl From<TextPath> for String {
+	fn from(path: TextPath) -> Self {
+		path.d
+	}
+}
+
+impl TextPath {
+
+	pub fn new(d: &str) -> Result<TextPath, SvgTextBoxError> {
+		TextPath::from_svg_path(d, 0.0, 0.0)
+	}
+
+	/// Follow the svg path data `d`, for a box whose top left is at (x, y) in the path's coordinates
+	pub fn from_svg_path(d: &str, x: f64, y: f64) -> Result<TextPath, SvgTextBoxError> {
+		let mut path = BezPath::from_svg(d)?;
+		path.apply_affine(Affine::translate((-x, -y)));
+		let mut corners: Vec<Point> = Vec::new();
+		let mut subpaths = 0;
+		path.flatten(PATH_TOLERANCE, |el| match el {
+			PathEl::MoveTo(p) => {
+				subpaths += 1;
+				if subpaths == 1 {
+					corners.push(p);
+				}
+			},
+			PathEl::LineTo(p) if subpaths == 1 => corners.push(p),
+			PathEl::ClosePath if subpaths == 1 => {
+				if let Some(first) = corners.first().cloned() {
+					corners.push(first);
+				}
+			},
+			_ => {}
+		});
+		let mut points: Vec<(f64, f64, f64)> = Vec::with_capacity(corners.len());
+		for p in corners {
+			let distance = match points.last() {
+				Some((x, y, d)) => d + (p.x - x).hypot(p.y - y),
+				None => 0.0
+			};
+			// corners in the same place would give a segment with no direction
+			if points.last().map(|(_, _, d)| distance > *d).unwrap_or(true) {
+				points.push((p.x, p.y, distance));
+			}
+		}
+		if points.len() < 2 {
+			return Err(SvgTextBoxError::InvalidPath(d.to_string()));
+		}
+		Ok(TextPath {
+			d: path.to_svg(),
+			points
+		})
+	}
+
+	/// The svg path data, relative to the top left of the box
+	pub fn d(&self) -> &str {
+		&self.d
+	}
+
+	/// How long the path is, in points
+	pub fn length(&self) -> f64 {
+		self.points.last()
+			.map(|(_, _, d)| *d)
+			.unwrap_or(0.0)
+	}
+
+	/// The furthest right and furthest down the path reaches, in points
+	pub(crate) fn bottom_right(&self) -> (f64, f64) {
+		self.points.iter()
+			.fold((0.0, 0.0), |(right, bottom), (x, y, _)| (x.max(right), y.max(bottom)))
+	}
+
+	/// The furthest left and furthest up the path reaches, in points
+	pub(crate) fn top_left(&self) -> (f64, f64) {
+		self.points.iter()
+			.fold((f64::INFINITY, f64::INFINITY), |(left, top), (x, y, _)| (x.min(left), y.min(top)))
+	}
+
+	/// The point `distance` along the path, moved `offset` to the right of its direction there.
+	/// Points beyond either end carry on in the direction of the path at that end.
+	pub(crate) fn place(&self, distance: f64, offset: f64) -> (f64, f64) {
+		let found = self.points.binary_search_by(|(_, _, d)| d.partial_cmp(&distance).unwrap_or(Ordering::Less));
+		let i = match found {
+			Ok(i) | Err(i) => i.max(1).min(self.points.len() - 1)
+		};
+		let (x0, y0, d0) = self.points[i - 1];
+		let (x1, y1, d1) = self.points[i];
+		let (dx, dy) = ((x1 - x0) / (d1 - d0), (y1 - y0) / (d1 - d0));
+		let along = distance - d0;
+		(x0 + dx * along - dy * offset, y0 + dy * along + dx * offset)
+	}
+
+	/// Fill the glyph outlines of `layout`, bent so that its first baseline follows the path,
+	/// with the box's top left at (x, y). Colours in the markup are not kept.
+	pub(crate) fn draw(&self, context: &cairo::Context, layout: &Layout, x: f64, y: f64) {
+		let baseline = f64::from(layout.get_baseline()) / f64::from(SCALE);
+		context.save();
+		context.new_path();
+		context.move_to(0.0, -baseline);
+		pangocairo::functions::layout_path(context, layout);
+		let outlines = context.copy_path_flat();
+		context.new_path();
+		let bent = |(u, v): (f64, f64)| {
+			let (px, py) = self.place(u, v);
+			(x + px, y + py)
+		};
+		let mut start = (0.0, 0.0);
+		let mut last = (0.0, 0.0);
+		for segment in outlines.iter() {
+			match segment {
+				PathSegment::MoveTo(p) => {
+					let (px, py) = bent(p);
+					context.move_to(px, py);
+					start = p;
+					last = p;
+				},
+				PathSegment::LineTo(p) => {
+					for (px, py) in bend_line(last, p).into_iter().map(bent) {
+						context.line_to(px, py);
+					}
+					last = p;
+				},
+				PathSegment::ClosePath => {
+					for (px, py) in bend_line(last, start).into_iter().map(bent) {
+						context.line_to(px, py);
+					}
+					context.close_path();
+					last = start;
+				},
+				// the path was flattened
+				PathSegment::CurveTo(..) => {}
+			}
+		}
+		context.fill();
+		context.restore();
+	}
+}
+
+/// The points along a straight line from `from` to `to`, not including `from`,
+/// close enough together that the line curves once it is bent
+fn bend_line(from: (f64, f64), to: (f64, f64)) -> Vec<(f64, f64)> {
+	let length = (to.0 - from.0).hypot(to.1 - from.1);
+	let steps = (length / BEND_STEP).ceil().max(1.0) as usize;
+	(1..=steps)
+		.map(|i| {
+			let t = i as f64 / steps as f64;
+			(from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t)
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn text_path_positions() {
+		let path = TextPath::new("M 0 0 H 100 V 50").unwrap();
+		assert_eq!(path.length(), 150.0);
+		assert_eq!(path.bottom_right(), (100.0, 50.0));
+		assert_eq!(path.top_left(), (0.0, 0.0));
+		assert_eq!(path.place(50.0, 0.0), (50.0, 0.0));
+		// to the right of the path is below it along the top, and to the left of it on the way down
+		assert_eq!(path.place(50.0, 10.0), (50.0, 10.0));
+		assert_eq!(path.place(125.0, 10.0), (90.0, 25.0));
+		// beyond the end, the path carries straight on
+		assert_eq!(path.place(160.0, 0.0), (100.0, 60.0));
+		let moved = TextPath::from_svg_path("M 10 20 H 110", 10.0, 20.0).unwrap();
+		assert_eq!(moved.place(0.0, 0.0), (0.0, 0.0));
+		assert!(TextPath::new("M 10 10").is_err());
+	}
+}
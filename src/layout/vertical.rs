@@ -0,0 +1,78 @@
+use serde::{Deserialize, Serialize};
+use pango::{Layout, SCALE};
+
+/// Where the text sits within the height of its box
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum VerticalAlignment {
+	/// the top of the first line is at the top of the box
+	Top,
+	/// the inked text is centred, ignoring the space above and below the glyphs
+	#[serde(alias = "middle")]
+	MiddleInk,
+	/// the lines of text are centred, including their ascent and descent
+	MiddleLogical,
+	/// the bottom of the last line is at the bottom of the box
+	Bottom,
+	/// the baseline of the first line is this many points below the top of the box
+	Baseline(f64),
+}
+
+impl Default for VerticalAlignment {
+	fn default() -> Self {
+		VerticalAlignment::MiddleInk
+	}
+}
+
+impl VerticalAlignment {
+
+	/// How far down to move `layout` within its own height, in points, when the space
+	/// between its paragraphs adds `paragraph_space` to the height of its lines
+	pub(crate) fn offset(&self, layout: &Layout, paragraph_space: i32) -> f64 {
+		let (ink_extents, logical_extents) = layout.get_extents();
+		let height = layout.get_height();
+		let offset = match self {
+			VerticalAlignment::Top => 0,
+			VerticalAlignment::MiddleInk => {
+				(height - ink_extents.height - paragraph_space) / 2 - ink_extents.y
+			},
+			VerticalAlignment::MiddleLogical => {
+				(height - logical_extents.height - paragraph_space) / 2 - logical_extents.y
+			},
+			VerticalAlignment::Bottom => {
+				height - (logical_extents.y + logical_extents.height + paragraph_space)
+			},
+			VerticalAlignment::Baseline(b) => {
+				let baseline = (b * f64::from(SCALE)).round() as i32;
+				baseline - layout.get_baseline()
+			}
+		};
+		f64::from(offset) / f64::from(SCALE)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::layout::tests::create_layout_for_testing;
+
+	#[test]
+	fn vertical_alignment_offsets() {
+		let layout = create_layout_for_testing();
+		let (_ink, logical) = layout.get_extents();
+		let height = f64::from(layout.get_height()) / f64::from(SCALE);
+		let text_height = f64::from(logical.height) / f64::from(SCALE);
+		assert_eq!(VerticalAlignment::Top.offset(&layout, 0), 0.0);
+		assert_eq!(VerticalAlignment::Bottom.offset(&layout, 0), height - text_height);
+		let middle = VerticalAlignment::MiddleLogical.offset(&layout, 0);
+		assert!((middle - (height - text_height) / 2.0).abs() < 0.01);
+		assert!(VerticalAlignment::MiddleInk.offset(&layout, 0) > 0.0);
+		let baseline = f64::from(layout.get_baseline()) / f64::from(SCALE);
+		let offset = VerticalAlignment::Baseline(40.0).offset(&layout, 0);
+		assert!((offset + baseline - 40.0).abs() < 0.01);
+		let parsed: VerticalAlignment = serde_json::from_str("{\"baseline\": 40}").unwrap();
+		assert_eq!(parsed, VerticalAlignment::Baseline(40.0));
+		let parsed: VerticalAlignment = serde_json::from_str("\"middle\"").unwrap();
+		assert_eq!(parsed, VerticalAlignment::MiddleInk);
+	}
+}
@@ -2,4 +2,7 @@ pub mod layout;
 pub mod textbox;
 pub mod xml_support;
 pub mod errors;
-mod pango_wrappers;
\ No newline at end of file
+mod pango_wrappers;
+mod hyphenation;
+mod fonts;
+mod units;
\ No newline at end of file
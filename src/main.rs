@@ -0,0 +1,222 @@
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process;
+use pango::SCALE;
+use svgtextbox::errors::SvgTextBoxError;
+use svgtextbox::layout::{OutputFormat, PngOptions};
+use svgtextbox::textbox::TextBox;
+use svgtextbox::xml_support::transform_xml_in;
+
+static USAGE: &str = "usage:
+    svgtextbox render <textbox.json> [--format svg|png|pdf|ps] [--dpi <dpi>] [--output <file>]
+    svgtextbox transform [<file.svg>] [--output <file>]
+    svgtextbox fit <textbox.json>
+
+Files may be given as - to read from stdin.
+
+exit codes:
+    0  success
+    1  an unexpected failure
+    2  invalid command line arguments
+    3  the text could not be fitted
+    4  no valid widths, heights or font sizes were given
+    5  the input was invalid
+    6  the svg document was invalid
+    7  the output could not be written";
+
+/// Exit codes for each kind of failure
+mod exit {
+    pub const USAGE: i32 = 2;
+    pub const COULD_NOT_FIT: i32 = 3;
+    pub const NO_VALID_SIZES: i32 = 4;
+    pub const BAD_INPUT: i32 = 5;
+    pub const XML: i32 = 6;
+    pub const OUTPUT: i32 = 7;
+    pub const OTHER: i32 = 1;
+}
+
+fn exit_code(e: &SvgTextBoxError) -> i32 {
+    match e {
+        SvgTextBoxError::FitFailed(_) => exit::COULD_NOT_FIT,
+        #[allow(deprecated)]
+        SvgTextBoxError::CouldNotFit => exit::COULD_NOT_FIT,
+        SvgTextBoxError::NoValidWidths
+        | SvgTextBoxError::NoValidHeights
+        | SvgTextBoxError::NoValidFontSizes => exit::NO_VALID_SIZES,
+        SvgTextBoxError::InvalidAlignment
+        | SvgTextBoxError::MissingBaseline
+        | SvgTextBoxError::InvalidAspectRatio(_)
+        | SvgTextBoxError::InvalidScale(_)
+        | SvgTextBoxError::InvalidLength(_)
+        | SvgTextBoxError::UnsupportedLanguage(_)
+        | SvgTextBoxError::FontFile(_)
+        | SvgTextBoxError::FontsUnsupported
+        | SvgTextBoxError::PangoTooOld(_)
+        | SvgTextBoxError::InvalidPath(_)
+        | SvgTextBoxError::PCSWhitespace
+        | SvgTextBoxError::BadChar(_)
+        | SvgTextBoxError::GlibErr(_)
+        | SvgTextBoxError::MissingMarkup
+        | SvgTextBoxError::BadIntParse(_)
+        | SvgTextBoxError::BadFloatParse(_)
+        | SvgTextBoxError::BadBoolParse(_)
+        | SvgTextBoxError::NulError(_)
+        | SvgTextBoxError::Utf8Error(_)
+        | SvgTextBoxError::FromUtf8Error(_)
+        | SvgTextBoxError::SerdeJsonError(_) => exit::BAD_INPUT,
+        SvgTextBoxError::XmlParseError(_)
+        | SvgTextBoxError::XmlNoRoot
+        | SvgTextBoxError::XsltError
+        | SvgTextBoxError::Xml
+        | SvgTextBoxError::BadReference(_)
+        | SvgTextBoxError::ConflictingAttributes(_)
+        | SvgTextBoxError::StackedTextboxes => exit::XML,
+        SvgTextBoxError::CairoError(_)
+        | SvgTextBoxError::CairoStatus(_)
+        | SvgTextBoxError::OutputStream
+        | SvgTextBoxError::CairoIoError(_)
+        | SvgTextBoxError::Io(_)
+        | SvgTextBoxError::NoPages
+        | SvgTextBoxError::PagesUnsupported => exit::OUTPUT,
+        SvgTextBoxError::UnexpectedNone
+        | SvgTextBoxError::Any(_)
+        | SvgTextBoxError::NSError => exit::OTHER,
+    }
+}
+
+/// Command line arguments split into positional arguments and `--flag value` options
+struct Args {
+    positional: Vec<String>,
+    format: Option<String>,
+    dpi: Option<String>,
+    output: Option<String>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item=String>) -> Result<Args, String> {
+        let mut positional = Vec::new();
+        let mut format = None;
+        let mut dpi = None;
+        let mut output = None;
+        while let Some(arg) = args.next() {
+            let target = match arg.as_str() {
+                "--format" | "-f" => &mut format,
+                "--dpi" => &mut dpi,
+                "--output" | "-o" => &mut output,
+                a if a.starts_with("--") => return Err(format!("unknown option {}", a)),
+                _ => {
+                    positional.push(arg);
+                    continue;
+                }
+            };
+            let value = args.next()
+                .ok_or_else(|| format!("{} needs a value", arg))?;
+            *target = Some(value);
+        }
+        Ok(Args {positional, format, dpi, output})
+    }
+}
+
+fn read_input(path: Option<&str>) -> Result<String, SvgTextBoxError> {
+    match path {
+        None | Some("-") => {
+            let mut s = String::new();
+            io::stdin().read_to_string(&mut s)?;
+            Ok(s)
+        },
+        Some(p) => Ok(fs::read_to_string(p)?)
+    }
+}
+
+fn write_output(path: Option<&str>, data: &[u8]) -> Result<(), SvgTextBoxError> {
+    match path {
+        None | Some("-") => io::stdout().write_all(data)?,
+        Some(p) => fs::write(p, data)?
+    }
+    Ok(())
+}
+
+fn read_textbox(path: Option<&str>) -> Result<TextBox, SvgTextBoxError> {
+    let src = read_input(path)?;
+    let textbox = serde_json::from_str(&src)?;
+    Ok(textbox)
+}
+
+fn output_format(args: &Args) -> Result<OutputFormat, String> {
+    let format = match args.format.as_ref().map(|f| f.to_lowercase()) {
+        None => "svg".to_string(),
+        Some(f) => f
+    };
+    let dpi = match &args.dpi {
+        Some(d) => Some(d.parse::<f64>().map_err(|_| format!("invalid dpi {}", d))?),
+        None => None
+    };
+    match format.as_str() {
+        "svg" => Ok(OutputFormat::Svg),
+        "png" => {
+            let options = dpi.map(PngOptions::with_dpi)
+                .unwrap_or_default();
+            Ok(OutputFormat::Png(options))
+        },
+        "pdf" => Ok(OutputFormat::Pdf),
+        "ps" => Ok(OutputFormat::Ps),
+        f => Err(format!("unknown format {}", f))
+    }
+}
+
+fn render(args: &Args) -> Result<(), SvgTextBoxError> {
+    let format = output_format(args)
+        .unwrap_or_else(|e| usage_error(&e));
+    let textbox = read_textbox(args.positional.get(1).map(String::as_str))?;
+    let document = textbox.to_document(&format)?;
+    write_output(args.output.as_deref(), &document.data)
+}
+
+fn transform(args: &Args) -> Result<(), SvgTextBoxError> {
+    let input = args.positional.get(1).map(String::as_str);
+    let src = read_input(input)?;
+    // fonts named in the document are found relative to it
+    let dir = match input {
+        None | Some("-") => Path::new(""),
+        Some(p) => Path::new(p).parent().unwrap_or_else(|| Path::new(""))
+    };
+    let transformed = transform_xml_in(&src, dir)?;
+    write_output(args.output.as_deref(), transformed.as_bytes())
+}
+
+fn fit(args: &Args) -> Result<(), SvgTextBoxError> {
+    let textbox = read_textbox(args.positional.get(1).map(String::as_str))?;
+    let image = textbox.to_svg_image()?;
+    let font_size = image.report.font_size()
+        .ok_or(SvgTextBoxError::UnexpectedNone)?;
+    println!("width: {}", image.width);
+    println!("height: {}", image.height);
+    println!("font-size: {}", f64::from(font_size) / f64::from(SCALE));
+    if let Some(t) = &image.report.truncation {
+        println!("truncated: {}", serde_json::to_string(&t.policy)?.trim_matches('"'));
+    }
+    Ok(())
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}\n\n{}", message, USAGE);
+    process::exit(exit::USAGE);
+}
+
+fn main() {
+    let args = Args::parse(env::args().skip(1))
+        .unwrap_or_else(|e| usage_error(&e));
+    let result = match args.positional.get(0).map(String::as_str) {
+        Some("render") => render(&args),
+        Some("transform") => transform(&args),
+        Some("fit") => fit(&args),
+        Some(c) => usage_error(&format!("unknown command {}", c)),
+        None => usage_error("no command given"),
+    };
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        process::exit(exit_code(&e));
+    }
+}
@@ -1,7 +1,9 @@
 use pango::{Alignment, FontDescription};
 use crate::errors::SvgTextBoxError;
+use crate::layout::Justification;
 use serde::de::{self, Visitor};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::convert::TryFrom;
 use std::str::FromStr;
 use std::fmt;
 use std::ops::Deref;
@@ -72,6 +74,107 @@ macro_rules! wrapper {
 	};
 }
 
+/// Horizontal alignment of text, including the logical sides and justification,
+/// which `AlignmentWrapper` can't hold
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum TextAlignment {
+    Left,
+    #[default]
+    Centre,
+    Right,
+    /// the side text starts from: left for left-to-right text, right for right-to-left
+    Start,
+    /// the side text ends at: right for left-to-right text, left for right-to-left
+    End,
+    /// both edges are aligned, except for the last line of each paragraph
+    Justify,
+    /// both edges are aligned, including the last line of each paragraph
+    JustifyAllLines,
+}
+
+impl TextAlignment {
+
+    /// The pango alignment for text whose base direction is right-to-left if `rtl`.
+    /// Justified text has its last lines aligned to the start.
+    pub fn to_pango(self, rtl: bool) -> Alignment {
+        match (self, rtl) {
+            (TextAlignment::Left, _) => Alignment::Left,
+            (TextAlignment::Centre, _) => Alignment::Center,
+            (TextAlignment::Right, _) => Alignment::Right,
+            (TextAlignment::End, false) => Alignment::Right,
+            (TextAlignment::End, true) => Alignment::Left,
+            (_, false) => Alignment::Left,
+            (_, true) => Alignment::Right,
+        }
+    }
+
+    pub fn justification(self) -> Justification {
+        match self {
+            TextAlignment::Justify => Justification::Justify,
+            TextAlignment::JustifyAllLines => Justification::JustifyAllLines,
+            _ => Justification::None
+        }
+    }
+}
+
+impl FromStr for TextAlignment {
+    type Err = SvgTextBoxError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "start" => Ok(TextAlignment::Start),
+            "end" => Ok(TextAlignment::End),
+            "justify" | "justified" => Ok(TextAlignment::Justify),
+            "justify-all-lines" => Ok(TextAlignment::JustifyAllLines),
+            _ => s.parse::<AlignmentWrapper>().map(TextAlignment::from)
+        }
+    }
+}
+
+impl TryFrom<String> for TextAlignment {
+    type Error = SvgTextBoxError;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<TextAlignment> for String {
+    fn from(a: TextAlignment) -> Self {
+        a.to_string()
+    }
+}
+
+impl From<Alignment> for TextAlignment {
+    fn from(a: Alignment) -> Self {
+        match a {
+            Alignment::Left => TextAlignment::Left,
+            Alignment::Right => TextAlignment::Right,
+            _ => TextAlignment::Centre
+        }
+    }
+}
+
+impl From<AlignmentWrapper> for TextAlignment {
+    fn from(a: AlignmentWrapper) -> Self {
+        TextAlignment::from(a.0)
+    }
+}
+
+impl fmt::Display for TextAlignment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            TextAlignment::Centre => "center",
+            TextAlignment::Left => "left",
+            TextAlignment::Right => "right",
+            TextAlignment::Start => "start",
+            TextAlignment::End => "end",
+            TextAlignment::Justify => "justify",
+            TextAlignment::JustifyAllLines => "justify-all-lines",
+        };
+        write!(f, "{}", s)
+    }
+}
+
 wrapper!(AlignmentWrapper, Alignment, AlignmentWrapperVisitor);
 wrapper!(FontDescriptionWrapper, FontDescription, FontDescriptionWrapperVisitor);
 
@@ -1,58 +1,79 @@
 use serde::de::{self, Visitor, MapAccess, SeqAccess};
 use std::fmt;
-use crate::layout::{RenderedTextbox, LayoutSource};
+use crate::layout::{shared_font_size, RenderedChain, RenderedTextbox, SvgTextMode, VerticalAlignment, Justification, FitMode, WrapStyle, OverflowPolicy, Shape, Exclusion, TextPath, WritingMode, TextDirection, FitCache, RenderedPng, PngOptions, RenderedDocument, OutputFormat, LayoutSource, FitStrategy, FirstAcceptable, LargestFontSize, SmallestAreaAtFontSize, BestAspectRatio, LetterSpacing};
 use lazy_static::lazy_static;
 use pango::{Alignment, FontDescription, SCALE};
 use regex::Regex;
-use serde::{Deserialize, Serialize, Deserializer};
+use serde::{Deserialize, Serialize, Deserializer, Serializer};
+use serde::ser::SerializeMap;
 use std::collections::{BTreeSet, HashMap};
 use std::convert::TryFrom;
 use std::default::Default;
 use std::fmt::Display;
-use std::num::NonZeroU16;
 use std::num::ParseIntError;
 use crate::errors::SvgTextBoxError;
+use crate::hyphenation::{hyphenate_markup, patterns};
 use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+use std::thread;
 
-pub use crate::pango_wrappers::{AlignmentWrapper, FontDescriptionWrapper};
+pub use crate::pango_wrappers::{AlignmentWrapper, FontDescriptionWrapper, TextAlignment};
+pub use crate::fonts::FontRegistry;
+pub use crate::units::{Length, LengthUnit, UnitConversion, DEFAULT_DPI, DEFAULT_EM};
+use crate::units::valid_dpi;
 
 /// a container to hold different groups of measurement units
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum UnitContainer {
-    AsSet(BTreeSet<NonZeroU16>),
+    AsSet(Vec<Length>),
     AsRange{
-        min: NonZeroU16,
-        max: NonZeroU16,
-        step: Option<usize>
+        min: Length,
+        max: Length,
+        step: Option<Length>
     },
 }
 
 
 impl UnitContainer {
 
-    pub fn iter<'a>(&'a self) -> Box<dyn Iterator<Item=u16> + 'a> {
+    /// A container holding only `value`
+    pub fn single(value: Length) -> Self {
+        UnitContainer::AsSet(vec![value])
+    }
+
+    /// All values, in ascending order, converted to `points * pango::SCALE`
+    pub fn iter<'a>(&'a self, conversion: UnitConversion) -> Box<dyn Iterator<Item=i32> + 'a> {
         match self {
             UnitContainer::AsSet(s) => {
-                Box::new(s.iter().map(|n| n.get()))
+                let sorted = s.iter()
+                    .map(|l| l.to_pango_units(conversion))
+                    .collect::<BTreeSet<i32>>();
+                Box::new(sorted.into_iter())
             },
             UnitContainer::AsRange{min, max, step} => {
-                let r = min.get()..=max.get();
-                match step {
-                    Some(u) => Box::new(r.step_by(*u)),
-                    None => Box::new(r)
-                }
+                let r = min.to_pango_units(conversion)..=max.to_pango_units(conversion);
+                let step = step
+                    .map(|s| s.to_pango_units(conversion))
+                    .unwrap_or(SCALE)
+                    .max(1);
+                Box::new(r.step_by(step as usize))
             }
         }
     }
 }
 
 
+/// Iterates over the values in whole points, kept for callers from before lengths had units.
+/// The values are only approximate: they are rounded, and pixels and ems are converted at
+/// 72 dpi and 12pt whatever the textbox's `dpi` and font. Prefer `iter` with the textbox's
+/// `unit_conversion()`, which gives exact sizes in pango units.
 impl IntoIterator for UnitContainer {
     type Item = u16;
     type IntoIter = std::collections::btree_set::IntoIter<Self::Item>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.iter()
+        self.iter(UnitConversion::default())
+            .map(|n| (f64::from(n) / f64::from(SCALE)).round() as u16)
             .collect::<BTreeSet<u16>>()
             .into_iter()
     }
@@ -100,6 +121,59 @@ impl PaddingSpecification {
 }
 
 
+/// How to choose between possible widths and heights.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub enum FitStrategySpecification {
+    /// the first width and height at which any font size fits
+    FirstAcceptable,
+    /// the width and height allowing the largest font size
+    LargestFontSize,
+    /// the smallest area reaching this font size
+    SmallestArea(Length),
+    /// the width and height closest to this width / height ratio
+    AspectRatio(#[serde(deserialize_with = "positive_ratio")] f64),
+}
+
+fn positive_ratio<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>
+{
+    let ratio = f64::deserialize(deserializer)?;
+    BestAspectRatio::new(ratio)
+        .map(|BestAspectRatio(r)| r)
+        .map_err(de::Error::custom)
+}
+
+fn positive_dpi<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>
+{
+    let dpi = f64::deserialize(deserializer)?;
+    valid_dpi(dpi)
+        .map_err(de::Error::custom)
+}
+
+impl Default for FitStrategySpecification {
+    fn default() -> Self {
+        FitStrategySpecification::FirstAcceptable
+    }
+}
+
+impl FitStrategySpecification {
+
+    /// The strategy this specifies, with lengths converted by `conversion`
+    pub fn to_strategy(&self, conversion: UnitConversion) -> Box<dyn FitStrategy> {
+        match self {
+            FitStrategySpecification::FirstAcceptable => Box::new(FirstAcceptable),
+            FitStrategySpecification::LargestFontSize => Box::new(LargestFontSize),
+            FitStrategySpecification::SmallestArea(f) => Box::new(SmallestAreaAtFontSize(f.to_pango_units(conversion))),
+            FitStrategySpecification::AspectRatio(r) => Box::new(BestAspectRatio(*r)),
+        }
+    }
+}
+
+
 /// Pango needs c-style strings (i.e. without null chars)
 /// and no unescaped ampersands. It will fail if incompatible strings
 /// are passed to various functions. PangoCompatibleString
@@ -129,6 +203,12 @@ impl PangoCompatibleString {
             Err(e) => Err(SvgTextBoxError::from(e)),
         }
     }
+
+    /// This string with soft hyphens between syllables, using the patterns for the language `lang`
+    pub fn hyphenated(&self, lang: &str) -> Result<Self, SvgTextBoxError> {
+        let hyphenated = hyphenate_markup(self.as_ref(), patterns(lang)?);
+        PangoCompatibleString::new(&hyphenated)
+    }
 }
 
 impl Display for PangoCompatibleString {
@@ -151,9 +231,9 @@ impl TryFrom<String> for PangoCompatibleString {
 }
 
 fn get_default_font_size() -> UnitContainer {
-    let min = NonZeroU16::new(10)
+    let min = Length::points(10.0)
         .unwrap();
-    let max = NonZeroU16::new(100)
+    let max = Length::points(100.0)
         .unwrap();
     UnitContainer::AsRange {
         min,
@@ -162,6 +242,14 @@ fn get_default_font_size() -> UnitContainer {
     }
 }
 
+fn get_default_dpi() -> f64 {
+    DEFAULT_DPI
+}
+
+fn get_default_line_height() -> f64 {
+    1.0
+}
+
 /// A textbox (possibly with flexible dimensions) which will have its text expand to fit.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TextBox {
@@ -174,15 +262,85 @@ pub struct TextBox {
     /// a wrapper around the font description to use
     #[serde(default, alias="font-desc")]
     pub font_desc: FontDescriptionWrapper,
-    /// the alignment of the text
+    /// the alignment of the text. This was an `AlignmentWrapper`, which can't be justified or
+    /// aligned to the start or end; one still converts into this, as does a `pango::Alignment`.
     #[serde(default)]
-    pub alignment: AlignmentWrapper,
+    pub alignment: TextAlignment,
     /// possible font sizes
     #[serde(default="get_default_font_size", alias="font-size")]
     pub font_size: UnitContainer,
     /// values for padding
     #[serde(default)]
     pub padding: PaddingSpecification,
+    /// the resolution used to convert pixel units
+    #[serde(default="get_default_dpi", deserialize_with = "positive_dpi")]
+    pub dpi: f64,
+    /// whether svg output should use glyph outlines or live text, which can't be vertical,
+    /// justified or letter spaced
+    #[serde(default, alias="text-mode")]
+    pub text_mode: SvgTextMode,
+    /// how to choose between possible widths and heights
+    #[serde(default, alias="fit-strategy")]
+    pub fit_strategy: FitStrategySpecification,
+    /// any constraint on the number of lines
+    #[serde(default, alias="fit-mode")]
+    pub fit_mode: FitMode,
+    /// whether to step down the font size rather than leave a paragraph
+    /// with a single word on its first or last line
+    #[serde(default, alias="avoid-stranded-lines")]
+    pub avoid_stranded_lines: bool,
+    /// how the text is broken into lines
+    #[serde(default, alias="wrap-style")]
+    pub wrap_style: WrapStyle,
+    /// where the text sits within the height of the textbox
+    #[serde(default, alias="vertical-alignment")]
+    pub vertical_alignment: VerticalAlignment,
+    /// the distance between baselines, as a multiple of the natural line height
+    #[serde(default="get_default_line_height", alias="line-height")]
+    pub line_height: f64,
+    /// extra space between letters
+    #[serde(default, alias="letter-spacing")]
+    pub letter_spacing: Option<Length>,
+    /// extra space between paragraphs, as a multiple of the line height
+    #[serde(default, alias="paragraph-spacing")]
+    pub paragraph_spacing: f64,
+    /// the language of the text, such as `de` or `nl-BE`, which also guides how pango shapes it
+    #[serde(default)]
+    pub lang: Option<String>,
+    /// whether long words may be hyphenated, using the patterns for `lang`
+    #[serde(default)]
+    pub hyphenate: bool,
+    /// what to do if the text does not fit at any font size
+    #[serde(default)]
+    pub overflow: OverflowPolicy,
+    /// a cache of earlier fits, which may be shared with other textboxes
+    #[serde(skip)]
+    pub fit_cache: Option<Arc<FitCache>>,
+    /// font files and directories, and aliases for them, to use as well as the installed fonts
+    #[serde(default)]
+    pub fonts: FontRegistry,
+    /// the outline the text is set inside, within the box. Text in a shape, or flowed around
+    /// exclusions, is not justified, balanced or kept from stranding lines.
+    #[serde(default)]
+    pub shape: Shape,
+    /// areas inside the box which the text flows around, as it does in a shape
+    #[serde(default)]
+    pub exclusions: Vec<Exclusion>,
+    /// a path for the text to follow as a single line, fitted to its length
+    #[serde(default)]
+    pub path: Option<TextPath>,
+    /// degrees clockwise to turn the text by; at 90 or 270 the width and height are those on the page
+    #[serde(default)]
+    pub rotation: f64,
+    /// which way lines of text run
+    #[serde(default)]
+    pub writing_mode: WritingMode,
+    /// the direction text runs in along its lines, which decides where `start` and `end` are
+    #[serde(default)]
+    pub direction: TextDirection,
+    /// begins the ids of elements in svg output, so several images can be put in one document
+    #[serde(default, alias="svg-id")]
+    pub svg_id: Option<String>,
     /// optional attributes for the background rectangle
     #[serde(flatten)]
     pub padding_attrs: HashMap<String, String>,
@@ -210,43 +368,270 @@ impl TextBox {
             markup,
             width,
             height,
-            alignment: AlignmentWrapper::default(),
+            alignment: TextAlignment::default(),
             font_desc: FontDescriptionWrapper::default(),
             font_size: UnitContainer::AsRange{
-                min: NonZeroU16::new(1).unwrap(),
-                max: NonZeroU16::new(100).unwrap(),
+                min: Length::points(1.0).unwrap(),
+                max: Length::points(100.0).unwrap(),
                 step: None
             },
             padding: PaddingSpecification::default(),
+            dpi: DEFAULT_DPI,
+            text_mode: SvgTextMode::default(),
+            fit_strategy: FitStrategySpecification::default(),
+            fit_mode: FitMode::default(),
+            avoid_stranded_lines: false,
+            wrap_style: WrapStyle::default(),
+            vertical_alignment: VerticalAlignment::default(),
+            line_height: get_default_line_height(),
+            letter_spacing: None,
+            paragraph_spacing: 0.0,
+            lang: None,
+            hyphenate: false,
+            overflow: OverflowPolicy::default(),
+            fit_cache: None,
+            fonts: FontRegistry::default(),
+            shape: Shape::default(),
+            exclusions: Vec::new(),
+            path: None,
+            rotation: 0.0,
+            writing_mode: WritingMode::default(),
+            direction: TextDirection::default(),
+            svg_id: None,
             padding_attrs: HashMap::new(),
         }
     }
 
     setter!(set_font_size, UnitContainer, font_size);
     setter!(set_font_desc, FontDescriptionWrapper, font_desc);
-    setter!(set_alignment, AlignmentWrapper, alignment);
+    setter!(set_alignment, TextAlignment, alignment);
     setter!(set_padding, PaddingSpecification, padding);
     setter!(set_padding_attrs, HashMap<String, String>, padding_attrs);
+    setter!(set_fit_strategy, FitStrategySpecification, fit_strategy);
+    setter!(set_fit_mode, FitMode, fit_mode);
+    setter!(set_avoid_stranded_lines, bool, avoid_stranded_lines);
+    setter!(set_wrap_style, WrapStyle, wrap_style);
+    setter!(set_text_mode, SvgTextMode, text_mode);
+    setter!(set_vertical_alignment, VerticalAlignment, vertical_alignment);
+    setter!(set_line_height, f64, line_height);
+    setter!(set_letter_spacing, Option<Length>, letter_spacing);
+    setter!(set_paragraph_spacing, f64, paragraph_spacing);
+    setter!(set_lang, Option<String>, lang);
+    setter!(set_hyphenate, bool, hyphenate);
+    setter!(set_overflow, OverflowPolicy, overflow);
+    setter!(set_fit_cache, Option<Arc<FitCache>>, fit_cache);
+    setter!(set_fonts, FontRegistry, fonts);
+    setter!(set_shape, Shape, shape);
+    setter!(set_exclusions, Vec<Exclusion>, exclusions);
+    setter!(set_path, Option<TextPath>, path);
+    setter!(set_rotation, f64, rotation);
+    setter!(set_writing_mode, WritingMode, writing_mode);
+    setter!(set_direction, TextDirection, direction);
+    setter!(set_svg_id, Option<String>, svg_id);
+
+    /// Set the resolution pixel lengths are converted at, which must be positive and finite
+    pub fn set_dpi(&mut self, dpi: f64) -> Result<&mut Self, SvgTextBoxError> {
+        self.dpi = valid_dpi(dpi)?;
+        Ok(self)
+    }
+
+    /// How lengths in this textbox are converted to points.
+    /// Ems are relative to the size of the font description, if it has one.
+    pub fn unit_conversion(&self) -> UnitConversion {
+        let size = self.font_desc.get_size();
+        let em = if size > 0 {
+            f64::from(size) / f64::from(SCALE)
+        } else {
+            DEFAULT_EM
+        };
+        UnitConversion {
+            dpi: self.dpi,
+            em
+        }
+    }
 
     pub fn to_svg_image(&self) -> Result<RenderedTextbox, SvgTextBoxError> {
         let mut image = RenderedTextbox::new(self)?;
-        if self.padding.has_values() && !self.padding_attrs.is_empty() {
-            image.insert_background_rect(&self.padding_attrs)?;
+        if let Some(attrs) = self.background_attrs() {
+            image.insert_background_rect(attrs)?;
         }
         Ok(image)
     }
+
+    pub fn to_png_image(&self, options: &PngOptions) -> Result<RenderedPng, SvgTextBoxError> {
+        RenderedPng::new(self, options)
+    }
+
+    /// Flow the text through boxes of each (width, height) in `geometries`, in order.
+    /// Like `width` and `height`, these include the padding, which every box has.
+    /// If no `font_size` is given, the largest possible font size at which all the text fits is used.
+    /// The boxes are plain rectangles, so a shape or exclusions can't be chained.
+    pub fn to_chained_svg_images(&self, geometries: &[(Length, Length)], font_size: Option<Length>) -> Result<RenderedChain, SvgTextBoxError> {
+        let conversion = self.unit_conversion();
+        let geometries = geometries.iter()
+            .map(|(w, h)| (
+                w.to_pango_units(conversion) - (self.padding.total_horizontal_padding() * SCALE),
+                h.to_pango_units(conversion) - (self.padding.total_vertical_padding() * SCALE)
+            ))
+            .collect::<Vec<(i32, i32)>>();
+        let font_size = font_size.map(|f| f.to_pango_units(conversion));
+        RenderedChain::new(self, &geometries, font_size)
+    }
+
+    pub fn to_document(&self, format: &OutputFormat) -> Result<RenderedDocument, SvgTextBoxError> {
+        RenderedDocument::new(self, format)
+    }
+}
+
+/// A group of textboxes which should all share a single font size
+#[derive(Debug, Clone, Default)]
+pub struct TextBoxGroup {
+    pub members: Vec<TextBox>,
+}
+
+impl TextBoxGroup {
+
+    pub fn new(members: Vec<TextBox>) -> Self {
+        TextBoxGroup {
+            members
+        }
+    }
+
+    /// The largest font size (in points) possible for every member which fits in all of them
+    pub fn shared_font_size(&self) -> Result<f64, SvgTextBoxError> {
+        let sources = self.members.iter()
+            .collect::<Vec<&TextBox>>();
+        let size = shared_font_size(&sources)?;
+        Ok(f64::from(size) / f64::from(SCALE))
+    }
+
+    /// Members with their font size fixed to the shared font size
+    pub fn fixed_members(&self) -> Result<Vec<TextBox>, SvgTextBoxError> {
+        let size = Length::points(self.shared_font_size()?)?;
+        let members = self.members.iter()
+            .map(|m| {
+                let mut m = m.clone();
+                m.set_font_size(UnitContainer::single(size));
+                m
+            })
+            .collect();
+        Ok(members)
+    }
+
+    pub fn to_svg_images(&self) -> Result<Vec<RenderedTextbox>, SvgTextBoxError> {
+        self.fixed_members()?
+            .iter()
+            .map(|m| m.to_svg_image())
+            .collect()
+    }
+}
+
+/// Many unrelated textboxes, rendered across several threads.
+/// Pango gives each thread its own default font map, and every layout gets its own context,
+/// so nothing from pango is shared between threads. A font description can't be sent to
+/// another thread, so each member is sent serialized, and read back on the thread rendering it.
+#[derive(Debug, Clone, Default)]
+pub struct TextBoxBatch {
+    pub members: Vec<TextBox>,
+    /// how many worker threads to use; 0 means one for each available cpu
+    pub threads: usize,
+}
+
+/// A textbox on its way to a worker thread, with its font description written as a string
+struct SentTextBox {
+    serialized: String,
+    /// not serialized, as it is shared
+    fit_cache: Option<Arc<FitCache>>,
+}
+
+impl SentTextBox {
+
+    fn new(textbox: &TextBox) -> Result<Self, SvgTextBoxError> {
+        Ok(SentTextBox {
+            serialized: serde_json::to_string(textbox)?,
+            fit_cache: textbox.fit_cache.clone()
+        })
+    }
+
+    /// The textbox again, with its font description rebuilt from the string
+    fn into_textbox(self) -> Result<TextBox, SvgTextBoxError> {
+        let mut textbox: TextBox = serde_json::from_str(&self.serialized)?;
+        textbox.fit_cache = self.fit_cache;
+        Ok(textbox)
+    }
+}
+
+impl TextBoxBatch {
+
+    pub fn new(members: Vec<TextBox>) -> Self {
+        TextBoxBatch {
+            members,
+            threads: 0
+        }
+    }
+
+    setter!(set_threads, usize, threads);
+
+    /// Apply `render` to every member, returning the results in the order of the members
+    pub fn render<T, F>(self, render: F) -> Vec<Result<T, SvgTextBoxError>>
+        where T: Send, F: Fn(&TextBox) -> Result<T, SvgTextBoxError> + Sync
+    {
+        let count = self.members.len();
+        let threads = match self.threads {
+            0 => thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            n => n
+        }.min(count).max(1);
+        let sent = self.members.iter()
+            .map(SentTextBox::new)
+            .collect::<Vec<Result<SentTextBox, SvgTextBoxError>>>();
+        let queue = Mutex::new(sent.into_iter().enumerate());
+        let render = &render;
+        let queue = &queue;
+        let mut finished = thread::scope(|scope| {
+            let workers = (0..threads)
+                .map(|_| scope.spawn(move || {
+                    let mut done = Vec::new();
+                    loop {
+                        // the lock is only held to take the next member
+                        let next = queue.lock()
+                            .unwrap_or_else(|e| e.into_inner())
+                            .next();
+                        match next {
+                            Some((i, sent)) => {
+                                let rendered = sent.and_then(SentTextBox::into_textbox)
+                                    .and_then(|textbox| render(&textbox));
+                                done.push((i, rendered));
+                            },
+                            None => return done
+                        }
+                    }
+                }))
+                .collect::<Vec<_>>();
+            workers.into_iter()
+                .flat_map(|w| w.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+                .collect::<Vec<(usize, Result<T, SvgTextBoxError>)>>()
+        });
+        finished.sort_by_key(|(i, _)| *i);
+        finished.into_iter()
+            .map(|(_, r)| r)
+            .collect()
+    }
+
+    pub fn to_svg_images(self) -> Vec<Result<RenderedTextbox, SvgTextBoxError>> {
+        self.render(TextBox::to_svg_image)
+    }
 }
 
 impl LayoutSource for TextBox {
 
     fn output_width(&self, layout_width: i32) -> f64 {
-        let unscaled = layout_width / SCALE;
-        f64::from(unscaled + self.padding.total_horizontal_padding())
+        let unscaled = f64::from(layout_width) / f64::from(SCALE);
+        unscaled + f64::from(self.padding.total_horizontal_padding())
     }
 
     fn output_height(&self, layout_height: i32) -> f64 {
-        let unscaled = layout_height / SCALE;
-        f64::from(unscaled + self.padding.total_vertical_padding())
+        let unscaled = f64::from(layout_height) / f64::from(SCALE);
+        unscaled + f64::from(self.padding.total_vertical_padding())
     }
 
     fn output_x(&self) -> f64 {
@@ -258,18 +643,16 @@ impl LayoutSource for TextBox {
     }
 
     fn possible_font_sizes<'a>(&'a self) -> Box<dyn Iterator<Item=i32> + 'a> {
-        Box::new(self.font_size.iter().map(|n| i32::from(n)*SCALE))
+        self.font_size.iter(self.unit_conversion())
     }
 
     fn possible_widths<'a>(&'a self) -> Box<dyn Iterator<Item=i32> + 'a> {
-        Box::new(self.width.iter()
-            .map(|n| i32::from(n) * SCALE)
+        Box::new(self.width.iter(self.unit_conversion())
             .map(move |n| n - (self.padding.total_horizontal_padding() * SCALE)))
     }
 
     fn possible_heights<'a>(&'a self) -> Box<dyn Iterator<Item=i32> + 'a> {
-        Box::new(self.width.iter()
-            .map(|n| i32::from(n) * SCALE)
+        Box::new(self.height.iter(self.unit_conversion())
             .map(move |n| n - (self.padding.total_vertical_padding() * SCALE)))
     }
 
@@ -282,8 +665,115 @@ impl LayoutSource for TextBox {
     }
 
     fn alignment(&self) -> Alignment {
-        let AlignmentWrapper(a) = self.alignment;
-        a
+        self.alignment.to_pango(self.direction.is_rtl(self.markup()))
+    }
+
+    fn justification(&self) -> Justification {
+        self.alignment.justification()
+    }
+
+    fn language(&self) -> Option<&str> {
+        self.lang.as_deref()
+    }
+
+    fn hyphenate(&self) -> bool {
+        self.hyphenate
+    }
+
+    fn line_height(&self) -> f64 {
+        self.line_height
+    }
+
+    fn letter_spacing(&self) -> LetterSpacing {
+        match self.letter_spacing {
+            // ems follow each font size tried, rather than the font description
+            Some(l) if l.unit == LengthUnit::Em => LetterSpacing::Em(l.value),
+            Some(l) => LetterSpacing::Fixed(l.to_pango_units(self.unit_conversion())),
+            None => LetterSpacing::default()
+        }
+    }
+
+    fn paragraph_spacing(&self) -> f64 {
+        self.paragraph_spacing
+    }
+
+    fn fit_strategy<'a>(&'a self) -> Box<dyn FitStrategy + 'a> {
+        self.fit_strategy.to_strategy(self.unit_conversion())
+    }
+
+    fn fit_mode(&self) -> FitMode {
+        self.fit_mode
+    }
+
+    fn avoid_stranded_lines(&self) -> bool {
+        self.avoid_stranded_lines
+    }
+
+    fn wrap_style(&self) -> WrapStyle {
+        self.wrap_style
+    }
+
+    fn text_mode(&self) -> SvgTextMode {
+        self.text_mode
+    }
+
+    fn background_attrs(&self) -> Option<&HashMap<String, String>> {
+        if self.padding.has_values() && !self.padding_attrs.is_empty() {
+            Some(&self.padding_attrs)
+        } else {
+            None
+        }
+    }
+
+    fn vertical_alignment(&self) -> VerticalAlignment {
+        self.vertical_alignment
+    }
+
+    fn overflow(&self) -> OverflowPolicy {
+        self.overflow
+    }
+
+    fn fit_cache(&self) -> Option<&FitCache> {
+        self.fit_cache.as_deref()
+    }
+
+    fn font_registry(&self) -> Option<&FontRegistry> {
+        if self.fonts.is_empty() {
+            None
+        } else {
+            Some(&self.fonts)
+        }
+    }
+
+    fn shape(&self) -> Option<&Shape> {
+        match self.shape {
+            Shape::Rectangle => None,
+            ref s => Some(s)
+        }
+    }
+
+    fn exclusions(&self) -> &[Exclusion] {
+        &self.exclusions
+    }
+
+    fn text_path(&self) -> Option<&TextPath> {
+        self.path.as_ref()
+    }
+
+    fn rotation(&self) -> f64 {
+        self.rotation
+    }
+
+    fn writing_mode(&self) -> WritingMode {
+        self.writing_mode
+    }
+
+    fn direction(&self) -> TextDirection {
+        self.direction
+    }
+
+    fn svg_id(&self) -> Option<&str> {
+        self.svg_id.as_deref()
     }
 }
 
@@ -296,6 +786,27 @@ impl <'de> Deserialize<'de> for PaddingSpecification {
     }
 }
 
+/// Written in the forms `UnitContainerVisitor` reads, so a serialized textbox can be read back
+impl Serialize for UnitContainer {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer
+    {
+        match self {
+            UnitContainer::AsSet(s) => s.serialize(serializer),
+            UnitContainer::AsRange{min, max, step} => {
+                let mut map = serializer.serialize_map(None)?;
+                map.serialize_entry("min", min)?;
+                map.serialize_entry("max", max)?;
+                if let Some(step) = step {
+                    map.serialize_entry("step", step)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
 impl <'de> Deserialize<'de> for UnitContainer {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -311,54 +822,50 @@ impl <'de> Visitor<'de> for UnitContainerVisitor {
     type Value = UnitContainer;
 
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter.write_str("a single integer, a sequence of integers, a string composed of integers seperated by a space, a map of min, max, and step")
+        formatter.write_str("a single length, a sequence of lengths, a string composed of lengths seperated by a space, a map of min, max, and step. Lengths are numbers optionally followed by one of pt, px, mm, cm, in or em")
     }
 
     fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
-        let integers = v.split_whitespace()
-                 .map(|n| n.parse::<NonZeroU16>())
-                 .collect::<Result<BTreeSet<NonZeroU16>, ParseIntError>>()
+        let lengths = v.split_whitespace()
+                 .map(|n| n.parse::<Length>())
+                 .collect::<Result<Vec<Length>, SvgTextBoxError>>()
                  .map_err(|_| de::Error::invalid_value(de::Unexpected::Str(v), &self))?;
-        if integers.len() == 0 {
+        if lengths.is_empty() {
             return Err(de::Error::invalid_length(0, &self));
         }
 
-        Ok(UnitContainer::AsSet(integers))
+        Ok(UnitContainer::AsSet(lengths))
     }
 
     fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
         where A: SeqAccess<'de>
     {
-        let mut values = BTreeSet::new();
+        let mut values = Vec::new();
         while let Some(v) = seq.next_element()? {
-            values.insert(v);
+            values.push(v);
         }
-        if values.len() == 0 {
+        if values.is_empty() {
             return Err(de::Error::invalid_length(0, &self));
         }
         Ok(UnitContainer::AsSet(values))
     }
 
     fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
-        let mut min: Option<NonZeroU16> = None;
-        let mut max: Option<NonZeroU16> = None;
-        let mut step: Option<usize> = None;
+        let mut min: Option<Length> = None;
+        let mut max: Option<Length> = None;
+        let mut step: Option<Length> = None;
 
         while let Some((k, v)) = map.next_entry()? {
             match k {
                 "min" => min = Some(v),
                 "max" => max = Some(v),
-                "step" => {
-                    let u = u16::from(v);
-                    let u = usize::from(u);
-                    step = Some(u);
-                },
+                "step" => step = Some(v),
                 _ => {}
             }
         }
 
-        let max = max.unwrap_or(NonZeroU16::new(std::u16::MAX).unwrap());
-        let min = min.unwrap_or(NonZeroU16::new(1).unwrap());
+        let max = max.unwrap_or(Length::points(f64::from(std::u16::MAX)).unwrap());
+        let min = min.unwrap_or(Length::points(1.0).unwrap());
 
         Ok(UnitContainer::AsRange{
             min,
@@ -368,15 +875,15 @@ impl <'de> Visitor<'de> for UnitContainerVisitor {
     }
 
     fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
-        let u = u16::try_from(v)
+        let l = Length::points(v as f64)
             .map_err(|_| de::Error::invalid_value(de::Unexpected::Unsigned(v as u64), &self))?;
-        let u = match NonZeroU16::new(u) {
-            Some(i) => Ok(i),
-            None => Err(de::Error::invalid_value(de::Unexpected::Unsigned(v as u64), &self))
-        }?;
-        let s = std::iter::once(u)
-            .collect::<BTreeSet<_>>();
-        Ok(UnitContainer::AsSet(s))
+        Ok(UnitContainer::single(l))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+        let l = Length::points(v)
+            .map_err(|_| de::Error::invalid_value(de::Unexpected::Float(v), &self))?;
+        Ok(UnitContainer::single(l))
     }
 }
 
@@ -472,6 +979,7 @@ impl <'de> Visitor<'de> for PaddingSpecificationVisitor {
 mod textbox_tests {
     use super::*;
     use pango::FontDescription;
+    use crate::layout::{FitReport, create_base_layout};
 
     #[test]
     fn paddedtextbox() {
@@ -489,7 +997,134 @@ mod textbox_tests {
             "stroke": "blue"
         }"##;
         let p: TextBox = serde_json::from_str(src).expect("a");
-        p.to_svg_image().expect("b");
+        let image = p.to_svg_image().expect("b");
+        assert!(image.src.contains("<rect fill=\"red\""));
+        let document = p.to_document(&OutputFormat::Svg).expect("c");
+        assert!(String::from_utf8(document.data).unwrap().contains("<rect fill=\"red\""));
+        // every surface draws the background, not only svg
+        let plain = p.clone()
+            .set_padding_attrs(HashMap::new())
+            .to_document(&OutputFormat::Pdf)
+            .unwrap();
+        let red = p.to_document(&OutputFormat::Pdf).unwrap();
+        assert_eq!(plain.page_sizes, red.page_sizes);
+        assert_ne!(plain.data, red.data);
+        // inside the blue stroke, the top padding of a png is the red fill, as bgra in memory
+        let png = p.to_png_image(&PngOptions::default()).unwrap();
+        let mut surface = cairo::ImageSurface::create_from_png(&mut png.data.as_slice()).unwrap();
+        let pixel = 5 * surface.get_stride() as usize + 2 * 4;
+        let data = surface.get_data().unwrap();
+        assert_eq!(&data[pixel..pixel + 4], &[0, 0, 255, 255]);
+        match p.to_png_image(&PngOptions::with_dpi(0.0)) {
+            Err(SvgTextBoxError::InvalidScale(s)) => assert_eq!(s, 0.0),
+            _ => panic!("expected a zero scale to be refused")
+        }
+    }
+
+    #[test]
+    fn padded_chain() {
+        let src = r##"{
+            "markup": "Hello World and hello again to everyone else in the world",
+            "width": 100,
+            "height": 100,
+            "padding": {"top": 5, "bottom": 5, "left": 10, "right": 10}
+        }"##;
+        let p: TextBox = serde_json::from_str(src).unwrap();
+        // as for a single box, the padding is inside the size given
+        let geometries = [box_size(120.0, 40.0), box_size(120.0, 210.0)];
+        let chain = p.to_chained_svg_images(&geometries, Some(points(12.0))).unwrap();
+        assert_eq!(chain.boxes[0].width, 120.0);
+        assert_eq!(chain.boxes[0].height, 40.0);
+        assert_eq!(chain.boxes[1].height, 210.0);
+        assert_eq!(chain.boxes[0].report.chosen().unwrap().width, 100 * SCALE);
+        let inches = (Length::new(2.0, LengthUnit::In).unwrap(), Length::new(1.0, LengthUnit::In).unwrap());
+        let chain = p.to_chained_svg_images(&[inches], None).unwrap();
+        assert_eq!((chain.boxes[0].width, chain.boxes[0].height), (144.0, 72.0));
+    }
+
+    fn points(value: f64) -> Length {
+        Length::points(value).unwrap()
+    }
+
+    fn box_size(width: f64, height: f64) -> (Length, Length) {
+        (points(width), points(height))
+    }
+
+    #[test]
+    fn chain_overflow_paragraphs() {
+        let src = r##"{
+            "markup": "One two three four\nFive six\u2029seven eight",
+            "width": 100,
+            "height": 100,
+            "paragraph-spacing": 1
+        }"##;
+        let p: TextBox = serde_json::from_str(src).unwrap();
+        let chain = p.to_chained_svg_images(&[box_size(100.0, 20.0)], Some(points(12.0))).unwrap();
+        let overflow = chain.overflow.unwrap();
+        // the space between paragraphs leaves the text as it was
+        assert!("One two three four\nFive six\u{2029}seven eight".ends_with(&overflow));
+        assert!(overflow.ends_with("six\u{2029}seven eight"));
+    }
+
+    #[test]
+    fn chain_shapes() {
+        let src = r##"{
+            "markup": "One two three four five six seven eight",
+            "width": 100,
+            "height": 100
+        }"##;
+        let p: TextBox = serde_json::from_str(src).unwrap();
+        let boxes = [box_size(100.0, 40.0), box_size(100.0, 100.0)];
+        assert!(p.to_chained_svg_images(&boxes, Some(points(12.0))).is_ok());
+        // the boxes of a chain are plain rectangles, which the text could not flow around
+        let mut circle = p.clone();
+        circle.set_shape(Shape::Circle);
+        match circle.to_chained_svg_images(&boxes, Some(points(12.0))) {
+            Err(SvgTextBoxError::ConflictingAttributes(_)) => (),
+            _ => panic!("expected a chain in a shape to be refused")
+        }
+        let mut logo = p;
+        logo.set_exclusions(vec![Exclusion::Rectangle {x: 60.0, y: 0.0, width: 40.0, height: 40.0}]);
+        match logo.to_chained_svg_images(&boxes, Some(points(12.0))) {
+            Err(SvgTextBoxError::ConflictingAttributes(_)) => (),
+            _ => panic!("expected a chain around exclusions to be refused")
+        }
+    }
+
+    #[test]
+    fn rotated_chain() {
+        let src = r##"{
+            "markup": "One two three four five six seven eight nine ten eleven twelve thirteen fourteen",
+            "width": 100,
+            "height": 100
+        }"##;
+        let p: TextBox = serde_json::from_str(src).unwrap();
+        let mut turned = p.clone();
+        turned.set_rotation(90.0);
+        // the lines run down the page, along the height of each box
+        let chain = turned.to_chained_svg_images(&[box_size(40.0, 100.0)], Some(points(12.0))).unwrap();
+        assert_eq!((chain.boxes[0].width, chain.boxes[0].height), (40.0, 100.0));
+        let chosen = chain.boxes[0].report.chosen().unwrap();
+        assert_eq!((chosen.width, chosen.height), (100 * SCALE, 40 * SCALE));
+        let upright = p.to_chained_svg_images(&[box_size(100.0, 40.0)], Some(points(12.0))).unwrap();
+        assert!(upright.overflow.is_some());
+        assert_eq!(chain.overflow, upright.overflow);
+    }
+
+    #[test]
+    fn chain_overflow_hyphenated() {
+        let src = r##"{
+            "markup": "Internationalisation considerations notwithstanding, extraordinarily comprehensive documentation",
+            "width": 100,
+            "height": 100,
+            "lang": "en",
+            "hyphenate": true
+        }"##;
+        let p: TextBox = serde_json::from_str(src).unwrap();
+        let chain = p.to_chained_svg_images(&[box_size(100.0, 20.0)], Some(points(12.0))).unwrap();
+        let overflow = chain.overflow.unwrap();
+        assert!(overflow.ends_with("comprehensive documentation"));
+        assert!(!overflow.contains('\u{ad}'));
     }
 
     #[test]
@@ -534,7 +1169,8 @@ mod textbox_tests {
         let mut textbox = RenderedTextbox {
             width: 50.0,
             height: 50.0,
-            src: src.to_string()
+            src: src.to_string(),
+            report: FitReport::default()
         };
 
         textbox.insert_background_rect(&attrs).unwrap();
@@ -546,6 +1182,604 @@ mod textbox_tests {
         assert_eq!(patched, expected);
     }
 
+    #[test]
+    fn fit_strategy_serde() {
+        let src = r##"{
+            "markup": "Hello World",
+            "width": [100, 300],
+            "height": 100,
+            "fit-strategy": "largest-font-size"
+        }"##;
+        let p: TextBox = serde_json::from_str(src).unwrap();
+        assert_eq!(p.fit_strategy, FitStrategySpecification::LargestFontSize);
+        let first = p.clone()
+            .set_fit_strategy(FitStrategySpecification::FirstAcceptable)
+            .to_svg_image()
+            .unwrap();
+        let largest = p.to_svg_image().unwrap();
+        assert_eq!(first.width, 100.0);
+        assert_eq!(largest.width, 300.0);
+
+        let s: FitStrategySpecification = serde_json::from_str(r#"{"smallest-area": 12}"#).unwrap();
+        assert_eq!(s, FitStrategySpecification::SmallestArea(Length::points(12.0).unwrap()));
+        let s: FitStrategySpecification = serde_json::from_str(r#"{"smallest-area": "10.5pt"}"#).unwrap();
+        assert_eq!(s, FitStrategySpecification::SmallestArea(Length::points(10.5).unwrap()));
+        let smallest = p.clone()
+            .set_fit_strategy(s)
+            .to_svg_image()
+            .unwrap();
+        assert!(smallest.report.font_size().unwrap() >= (10.5 * f64::from(SCALE)) as i32);
+        let s: FitStrategySpecification = serde_json::from_str(r#"{"aspect-ratio": 1.5}"#).unwrap();
+        assert_eq!(s, FitStrategySpecification::AspectRatio(1.5));
+        assert!(serde_json::from_str::<FitStrategySpecification>(r#"{"aspect-ratio": 0}"#).is_err());
+        assert!(serde_json::from_str::<FitStrategySpecification>(r#"{"aspect-ratio": -2.0}"#).is_err());
+        assert!(BestAspectRatio::new(0.0).is_err());
+    }
+
+    #[test]
+    fn justified_alignment() {
+        let a: TextAlignment = serde_json::from_str("\"justify-all-lines\"").unwrap();
+        assert_eq!(a, TextAlignment::JustifyAllLines);
+        assert_eq!(serde_json::to_string(&a).unwrap(), "\"justify-all-lines\"");
+        assert_eq!("end".parse::<TextAlignment>().unwrap(), TextAlignment::End);
+        assert_eq!("Centre".parse::<TextAlignment>().unwrap(), TextAlignment::Centre);
+        assert!("middle".parse::<TextAlignment>().is_err());
+        // the wrapper still holds only what pango can
+        assert_eq!(*"right".parse::<AlignmentWrapper>().unwrap(), Alignment::Right);
+        assert!("justify".parse::<AlignmentWrapper>().is_err());
+        assert_eq!(TextAlignment::from(AlignmentWrapper(Alignment::Left)), TextAlignment::Left);
+        assert_eq!(TextAlignment::Start.to_pango(false), Alignment::Left);
+        assert_eq!(TextAlignment::Start.to_pango(true), Alignment::Right);
+        assert_eq!(TextAlignment::End.to_pango(false), Alignment::Right);
+        assert_eq!(TextAlignment::Justify.to_pango(false), Alignment::Left);
+        assert_eq!(TextAlignment::Justify.justification(), Justification::Justify);
+        assert_eq!(TextAlignment::Right.justification(), Justification::None);
+
+        let mut tb = TextBox::new(
+            PangoCompatibleString::new("A paragraph of several words which will need to wrap").unwrap(),
+            UnitContainer::single(Length::points(100.0).unwrap()),
+            UnitContainer::single(Length::points(100.0).unwrap())
+        );
+        let ragged = tb.to_svg_image().unwrap();
+        tb.set_alignment(TextAlignment::JustifyAllLines);
+        let justified = tb.to_svg_image().unwrap();
+        // justification only stretches lines, so the same font size fits
+        assert_eq!(
+            ragged.report.chosen().unwrap().largest_fit,
+            justified.report.chosen().unwrap().largest_fit
+        );
+    }
+
+    #[test]
+    fn spacing() {
+        let src = r##"{
+            "markup": "First paragraph\nSecond paragraph",
+            "width": 300,
+            "height": 300,
+            "font-size": 20
+        }"##;
+        let plain: TextBox = serde_json::from_str(src).unwrap();
+        let spaced: TextBox = serde_json::from_str(r##"{
+            "markup": "First paragraph\nSecond paragraph",
+            "width": 300,
+            "height": 300,
+            "font-size": 20,
+            "line-height": 1.5,
+            "letter-spacing": "0.1em",
+            "paragraph-spacing": 1
+        }"##).unwrap();
+        assert_eq!(spaced.line_height, 1.5);
+        assert_eq!(spaced.letter_spacing, Some(Length::new(0.1, LengthUnit::Em).unwrap()));
+        assert_eq!(spaced.paragraph_spacing, 1.0);
+        // ems are relative to each font size, not the default em
+        assert_eq!(spaced.letter_spacing(), LetterSpacing::Em(0.1));
+        assert_eq!(spaced.letter_spacing().at(20 * SCALE), 2 * SCALE);
+        assert_eq!(spaced.letter_spacing().at(10 * SCALE), SCALE);
+        let mut fixed = spaced.clone();
+        fixed.set_letter_spacing(Some(Length::points(1.0).unwrap()));
+        assert_eq!(fixed.letter_spacing().at(20 * SCALE), SCALE);
+
+        let plain_layout = crate::layout::create_base_layout(&plain).unwrap();
+        if cfg!(feature = "v1_44") {
+            let spaced_layout = crate::layout::create_base_layout(&spaced).unwrap();
+            // the text and its lines are left alone
+            assert_eq!(spaced_layout.get_text(), plain_layout.get_text());
+            assert_eq!(spaced_layout.get_line_count(), plain_layout.get_line_count());
+            let (_, plain_extents) = plain_layout.get_extents();
+            let (_, spaced_extents) = spaced_layout.get_extents();
+            assert!(spaced_extents.height > plain_extents.height * 5 / 4);
+            // and the second paragraph moves down by a line and a half of 20pt text
+            let gap = crate::layout::paragraph_gap(&spaced_layout, spaced.paragraph_spacing());
+            assert!(gap > 20 * SCALE);
+            assert_eq!(crate::layout::paragraph_offsets(&spaced_layout, gap), vec![0, gap]);
+        } else {
+            assert!(crate::layout::create_base_layout(&spaced).is_err());
+        }
+    }
+
+    #[test]
+    fn fit_modes() {
+        let headline: TextBox = serde_json::from_str(r##"{
+            "markup": "A rather long headline",
+            "width": 200,
+            "height": 200,
+            "fit-mode": "single-line"
+        }"##).unwrap();
+        assert_eq!(headline.fit_mode, FitMode::SingleLine);
+        let single = headline.to_svg_image().unwrap();
+        let wrapped = headline.clone()
+            .set_fit_mode(FitMode::FillBox)
+            .to_svg_image()
+            .unwrap();
+        assert!(single.report.chosen().unwrap().largest_fit < wrapped.report.chosen().unwrap().largest_fit);
+
+        let m: FitMode = serde_json::from_str(r#"{"max-lines": 3}"#).unwrap();
+        assert_eq!(m, FitMode::MaxLines(3));
+    }
+
+    #[test]
+    fn balanced_wrap_style() {
+        let title: TextBox = serde_json::from_str(r##"{
+            "markup": "A centred title which wraps onto a second line",
+            "width": 200,
+            "height": 100,
+            "font-size": 18,
+            "wrap-style": "balanced"
+        }"##).unwrap();
+        assert_eq!(title.wrap_style, WrapStyle::Balanced);
+        let balanced = title.to_svg_image().unwrap();
+        let greedy = title.clone()
+            .set_wrap_style(WrapStyle::Greedy)
+            .to_svg_image()
+            .unwrap();
+        assert_eq!(balanced.width, greedy.width);
+        assert_eq!(
+            balanced.report.chosen().unwrap().largest_fit,
+            greedy.report.chosen().unwrap().largest_fit
+        );
+        assert_ne!(balanced.src, greedy.src);
+    }
+
+    #[test]
+    fn hyphenation() {
+        let compound: TextBox = serde_json::from_str(r##"{
+            "markup": "<b>Donaudampfschifffahrtsgesellschaft</b>",
+            "width": 100,
+            "height": 100,
+            "lang": "de",
+            "text-mode": "live-text"
+        }"##).unwrap();
+        let unbroken = compound.to_svg_image().unwrap();
+        let hyphenated = compound.clone()
+            .set_hyphenate(true)
+            .to_svg_image()
+            .unwrap();
+        assert!(
+            hyphenated.report.chosen().unwrap().largest_fit > unbroken.report.chosen().unwrap().largest_fit
+        );
+        assert!(hyphenated.src.contains("-</tspan>"));
+        assert!(!hyphenated.src.contains('\u{ad}'));
+
+        let pcs = compound.markup.hyphenated("de").unwrap();
+        assert!(pcs.as_ref().starts_with("<b>Do"));
+        assert!(compound.markup.hyphenated("tlh").is_err());
+        assert!(compound.clone()
+            .set_hyphenate(true)
+            .set_lang(Some("tlh".to_string()))
+            .to_svg_image()
+            .is_err());
+    }
+
+    #[test]
+    fn overflow_policy() {
+        let long_name: TextBox = serde_json::from_str(r##"{
+            "markup": "An extremely long product name which could never fit in such a small box",
+            "width": 60,
+            "height": 20,
+            "font-size": {"min": 10, "max": 20},
+            "overflow": "ellipsize-end",
+            "text-mode": "live-text"
+        }"##).unwrap();
+        assert_eq!(long_name.overflow, OverflowPolicy::EllipsizeEnd);
+        match long_name.clone().set_overflow(OverflowPolicy::Error).to_svg_image() {
+            Err(SvgTextBoxError::FitFailed(report)) => assert!(report.truncation.is_none()),
+            _ => panic!("expected the fit to fail")
+        }
+
+        let ellipsized = long_name.to_svg_image().unwrap();
+        let truncation = ellipsized.report.truncation.as_ref().unwrap();
+        assert_eq!(truncation.policy, OverflowPolicy::EllipsizeEnd);
+        assert_eq!(truncation.font_size, 10 * SCALE);
+        assert_eq!(ellipsized.report.font_size(), Some(10 * SCALE));
+        assert!(ellipsized.src.contains('\u{2026}'));
+        // the ellipsis stands in for the hidden text, which is not written as well
+        assert!(!ellipsized.src.contains("small box"));
+
+        let clipped = long_name.clone()
+            .set_overflow(OverflowPolicy::Clip)
+            .to_svg_image()
+            .unwrap();
+        assert_eq!(clipped.report.truncation.unwrap().policy, OverflowPolicy::Clip);
+        assert!(clipped.src.contains("clip-path"));
+        assert!(!clipped.src.contains('\u{2026}'));
+    }
+
+    #[test]
+    fn shapes() {
+        let badge: TextBox = serde_json::from_str(r##"{
+            "markup": "Official sponsor of the regional championship",
+            "width": 120,
+            "height": 120,
+            "alignment": "center",
+            "text-mode": "live-text"
+        }"##).unwrap();
+        let boxed = badge.to_svg_image().unwrap();
+        let circle = badge.clone()
+            .set_shape(Shape::Circle)
+            .to_svg_image()
+            .unwrap();
+        assert!(circle.report.font_size().unwrap() < boxed.report.font_size().unwrap());
+        assert!(circle.report.flowed);
+        assert!(!boxed.report.flowed);
+        assert_eq!(circle.width, boxed.width);
+        assert!(!circle.src.contains('\u{fffc}'));
+        assert!(circle.src.contains("championship"));
+
+        let pennant: TextBox = serde_json::from_str(r##"{
+            "markup": "Go team",
+            "width": 120,
+            "height": 60,
+            "shape": {"polygon": [[0, 0], [120, 30], [0, 60]]}
+        }"##).unwrap();
+        assert_eq!(pennant.shape, Shape::Polygon(vec![(0.0, 0.0), (120.0, 30.0), (0.0, 60.0)]));
+        assert!(pennant.to_svg_image().is_ok());
+    }
+
+    #[test]
+    fn exclusions() {
+        let card: TextBox = serde_json::from_str(r##"{
+            "markup": "Wholesale orders of twelve or more come with free delivery",
+            "width": 160,
+            "height": 80,
+            "exclusions": [{"rectangle": {"x": 120, "y": 0, "width": 40, "height": 40}}]
+        }"##).unwrap();
+        assert_eq!(card.exclusions, vec![Exclusion::Rectangle {x: 120.0, y: 0.0, width: 40.0, height: 40.0}]);
+        let around = card.to_svg_image().unwrap();
+        let plain = card.clone()
+            .set_exclusions(Vec::<Exclusion>::new())
+            .to_svg_image()
+            .unwrap();
+        assert!(around.report.font_size().unwrap() <= plain.report.font_size().unwrap());
+        assert!(around.report.flowed);
+        assert_eq!(around.width, plain.width);
+    }
+
+    #[test]
+    fn text_path() {
+        let seal: TextBox = serde_json::from_str(r##"{
+            "markup": "Established in the year of the great flood",
+            "width": 200,
+            "height": 30,
+            "path": "M 10 100 A 90 90 0 0 1 190 100"
+        }"##).unwrap();
+        let path = seal.path.clone().unwrap();
+        assert!((path.length() - std::f64::consts::PI * 90.0).abs() < 1.0);
+        let image = seal.to_svg_image().unwrap();
+        assert_eq!(image.report.chosen().unwrap().width, (path.length() * f64::from(SCALE)) as i32);
+        let short = seal.clone()
+            .set_path(TextPath::new("M 10 100 H 60").unwrap())
+            .to_svg_image()
+            .unwrap();
+        assert!(short.report.font_size().unwrap() < image.report.font_size().unwrap());
+        // glyphs on a path along the top of the box rise above it, so the box grows upwards
+        let along_top = seal.clone()
+            .set_path(TextPath::new("M 0 0 H 150").unwrap())
+            .to_svg_image()
+            .unwrap();
+        assert!(along_top.height > 30.0);
+        let live = seal.clone()
+            .set_text_mode(SvgTextMode::LiveText)
+            .to_svg_image()
+            .unwrap();
+        assert!(live.src.contains("<textPath href=\"#text-path\""));
+        let named = seal.clone()
+            .set_text_mode(SvgTextMode::LiveText)
+            .set_svg_id("seal".to_string())
+            .to_svg_image()
+            .unwrap();
+        assert!(named.src.contains("<path id=\"seal-text-path\""));
+        assert!(named.src.contains("<textPath href=\"#seal-text-path\""));
+        assert!(serde_json::from_str::<TextBox>(r#"{"markup": "Seal", "width": 100, "height": 30, "path": "M 10"}"#).is_err());
+    }
+
+    #[test]
+    fn rotation() {
+        let spine: TextBox = serde_json::from_str(r##"{
+            "markup": "Assorted herbal teas",
+            "width": 30,
+            "height": 200,
+            "rotation": 90
+        }"##).unwrap();
+        let image = spine.to_svg_image().unwrap();
+        assert_eq!((image.width, image.height), (30.0, 200.0));
+        let chosen = image.report.chosen().unwrap();
+        assert_eq!((chosen.width, chosen.height), (200 * SCALE, 30 * SCALE));
+
+        let tilted = spine.clone()
+            .set_rotation(30.0)
+            .to_svg_image()
+            .unwrap();
+        assert!(tilted.width > 30.0 && tilted.height > 200.0);
+        let live = spine.clone()
+            .set_text_mode(SvgTextMode::LiveText)
+            .to_svg_image()
+            .unwrap();
+        assert!(live.src.contains("rotate(90)"));
+    }
+
+    #[test]
+    fn vertical_writing_mode() {
+        let label: TextBox = serde_json::from_str(r##"{
+            "markup": "緑茶と抹茶の詰め合わせ",
+            "width": 40,
+            "height": 200,
+            "writing-mode": "vertical"
+        }"##).unwrap();
+        let image = label.to_svg_image().unwrap();
+        assert_eq!((image.width, image.height), (40.0, 200.0));
+        let chosen = image.report.chosen().unwrap();
+        assert_eq!((chosen.width, chosen.height), (200 * SCALE, 40 * SCALE));
+        // the lines lie on their side, but the glyphs are turned back upright
+        let context = create_base_layout(&label).unwrap().get_context().unwrap();
+        assert_eq!(context.get_base_gravity(), pango::Gravity::East);
+        assert_eq!(context.get_gravity(), pango::Gravity::East);
+        assert_eq!(context.get_gravity_hint(), pango::GravityHint::Natural);
+        // live text can't turn them upright, so it is refused
+        match label.clone().set_text_mode(SvgTextMode::LiveText).to_svg_image() {
+            Err(SvgTextBoxError::ConflictingAttributes(_)) => {},
+            _ => panic!("expected vertical live text to be refused")
+        }
+        let upside_down: TextBox = serde_json::from_str(r##"{
+            "markup": "緑茶",
+            "width": 40,
+            "height": 200,
+            "rotation": 180,
+            "writing-mode": "vertical-rl"
+        }"##).unwrap();
+        assert_eq!(upside_down.writing_mode, WritingMode::Vertical);
+        assert_eq!(upside_down.to_svg_image().unwrap().width, 40.0);
+    }
+
+    #[test]
+    fn right_to_left() {
+        let product: TextBox = serde_json::from_str(r##"{
+            "markup": "שמן זית כתית מעולה",
+            "width": 200,
+            "height": 40,
+            "alignment": "start",
+            "lang": "he"
+        }"##).unwrap();
+        assert_eq!(product.direction, TextDirection::Auto);
+        assert_eq!(product.alignment(), Alignment::Right);
+        let layout = create_base_layout(&product).unwrap();
+        assert_eq!(layout.get_context().unwrap().get_base_dir(), pango::Direction::Rtl);
+        layout.set_width(200 * SCALE);
+        let (_ink, logical) = layout.get_extents();
+        assert!(logical.x > 0);
+        assert!(product.to_svg_image().unwrap().report.font_size().is_some());
+        assert!(product.clone().set_shape(Shape::Ellipse).to_svg_image().is_ok());
+        assert_eq!(product.clone().set_direction(TextDirection::Ltr).alignment(), Alignment::Left);
+    }
+
+    #[test]
+    fn mixed_direction_text() {
+        // a Hebrew name inside English runs left to right, unless told otherwise
+        let mixed: TextBox = serde_json::from_str(r##"{
+            "markup": "Olive oil <b>שמן זית</b> 500 ml",
+            "width": [120, 200],
+            "height": 60,
+            "alignment": "end"
+        }"##).unwrap();
+        assert_eq!(mixed.alignment(), Alignment::Right);
+        let mut rtl = mixed.clone();
+        rtl.set_direction(TextDirection::Rtl);
+        assert_eq!(rtl.alignment(), Alignment::Left);
+        assert!(mixed.to_svg_image().unwrap().report.font_size().is_some());
+        assert!(rtl.to_svg_image().unwrap().report.font_size().is_some());
+
+        let arabic: TextBox = serde_json::from_str(r##"{
+            "markup": "زيت زيتون Extra Virgin 500ml",
+            "width": 200,
+            "height": 60,
+            "alignment": "start",
+            "direction": "auto"
+        }"##).unwrap();
+        assert_eq!(arabic.alignment(), Alignment::Right);
+        let live = arabic.clone()
+            .set_text_mode(SvgTextMode::LiveText)
+            .to_svg_image()
+            .unwrap();
+        assert!(live.src.contains("زيت زيتون"));
+        assert!(live.src.contains("Extra Virgin"));
+    }
+
+    #[test]
+    fn fit_cache() {
+        let cache = Arc::new(FitCache::new());
+        let mut badge: TextBox = serde_json::from_str(r##"{
+            "markup": "Visitor",
+            "width": [80, 120],
+            "height": 40,
+            "fit-strategy": "largest-font-size"
+        }"##).unwrap();
+        badge.set_fit_cache(cache.clone());
+        let first = badge.to_svg_image().unwrap();
+        let second = badge.to_svg_image().unwrap();
+        assert!(!first.report.cached);
+        assert!(second.report.cached);
+        assert_eq!(first.src, second.src);
+        assert_eq!(first.report.font_size(), second.report.font_size());
+        assert_eq!(cache.len(), 1);
+
+        let mut other = badge.clone();
+        other.markup = PangoCompatibleString::new("Staff").unwrap();
+        let other = other.to_svg_image().unwrap();
+        assert!(!other.report.cached);
+        assert_eq!(cache.len(), 2);
+        // text on a path is fitted to the path, so it is not mistaken for the same text in the box
+        let on_path = badge.clone()
+            .set_path(TextPath::new("M 0 20 H 200").unwrap())
+            .to_svg_image()
+            .unwrap();
+        assert!(!on_path.report.cached);
+        assert_eq!(cache.len(), 3);
+
+        let path = std::env::temp_dir().join(format!("svgtextbox-fit-cache-{}.json", std::process::id()));
+        let saved = FitCache::open(&path).unwrap();
+        assert!(saved.is_empty());
+        badge.set_fit_cache(Arc::new(saved));
+        badge.to_svg_image().unwrap();
+        badge.fit_cache.as_ref().unwrap().save().unwrap();
+        let reopened = FitCache::open(&path).unwrap();
+        assert_eq!(reopened.len(), 1);
+        assert!(!reopened.clear_if_fonts_changed());
+        reopened.clear();
+        assert!(reopened.is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn textbox_batch() {
+        fn is_send<T: Send>() {}
+        is_send::<SvgTextBoxError>();
+
+        let members = (0..12)
+            .map(|i| {
+                let mut tb = TextBox::new(
+                    PangoCompatibleString::new(&format!("Label number {}", i)).unwrap(),
+                    UnitContainer::single(Length::points(f64::from(50 + i * 10)).unwrap()),
+                    UnitContainer::single(Length::points(50.0).unwrap())
+                );
+                if i == 5 {
+                    // far too big to fit
+                    tb.set_font_size(UnitContainer::single(Length::points(200.0).unwrap()));
+                }
+                tb
+            })
+            .collect::<Vec<TextBox>>();
+        let expected = members.iter()
+            .map(|m| m.to_svg_image().map(|r| r.src))
+            .collect::<Vec<_>>();
+        let mut batch = TextBoxBatch::new(members);
+        batch.set_threads(4usize);
+        let results = batch.to_svg_images();
+        assert_eq!(results.len(), 12);
+        for (i, (result, expected)) in results.iter().zip(expected.iter()).enumerate() {
+            match (result, expected) {
+                (Ok(r), Ok(e)) => {
+                    assert_eq!(r.width, f64::from(50 + i as u32 * 10));
+                    assert_eq!(&r.src, e);
+                },
+                (Err(SvgTextBoxError::FitFailed(_)), Err(_)) => assert_eq!(i, 5),
+                _ => panic!("result {} differs from rendering alone", i)
+            }
+        }
+    }
+
+    #[test]
+    fn font_description_between_threads() {
+        let original: FontDescriptionWrapper = "Serif Bold 12".parse().unwrap();
+        let sent = original.to_string();
+        // the description is rebuilt from its string on the other thread
+        let changed = thread::spawn(move || {
+            let mut fd = sent.parse::<FontDescriptionWrapper>().unwrap().0;
+            fd.set_size(20 * SCALE);
+            fd.to_string()
+        }).join().unwrap();
+        assert_eq!(changed, "Serif Bold 20");
+        assert_eq!(original.to_string(), "Serif Bold 12");
+    }
+
+    #[test]
+    fn serialized_textbox() {
+        let src = r##"{
+            "markup": "Hello &amp; <b>World</b>",
+            "width": "100 120pt",
+            "height": {"min": 20, "max": 60, "step": "2mm"},
+            "font-desc": "Serif Bold",
+            "alignment": "justify",
+            "padding": {"top": 5, "left": 10},
+            "letter-spacing": "0.1em",
+            "fill": "blue"
+        }"##;
+        let p: TextBox = serde_json::from_str(src).unwrap();
+        // a batch sends textboxes to its threads like this, so they must read back the same
+        let serialized = serde_json::to_string(&p).unwrap();
+        let read_back: TextBox = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(serde_json::to_string(&read_back).unwrap(), serialized);
+        assert_eq!(read_back.width, p.width);
+        assert_eq!(read_back.height, p.height);
+        assert_eq!(read_back.font_desc.to_string(), "Serif Bold");
+        assert_eq!(read_back.to_svg_image().unwrap().src, p.to_svg_image().unwrap().src);
+    }
+
+    #[test]
+    fn textbox_group() {
+        let narrow: TextBox = serde_json::from_str(r#"{
+            "markup": "Hello World",
+            "width": 50,
+            "height": 50
+        }"#).unwrap();
+        let wide: TextBox = serde_json::from_str(r#"{
+            "markup": "Hello World",
+            "width": 300,
+            "height": 150
+        }"#).unwrap();
+        let narrow_alone = TextBoxGroup::new(vec![narrow.clone()])
+            .shared_font_size()
+            .unwrap();
+        let group = TextBoxGroup::new(vec![wide, narrow]);
+        assert_eq!(group.shared_font_size().unwrap(), narrow_alone);
+        let images = group.to_svg_images().unwrap();
+        assert_eq!(images.len(), 2);
+        for image in images.iter() {
+            let size = image.report.chosen().unwrap().largest_fit.unwrap();
+            assert_eq!(f64::from(size), narrow_alone * f64::from(SCALE));
+        }
+    }
+
+    #[test]
+    fn unit_container_units() {
+        let src = r##"{
+            "markup": "Hello World",
+            "width": "50mm 2in",
+            "height": {"min": "10mm", "max": "20mm", "step": "5mm"},
+            "font-size": [10.5, "1em", "16px"],
+            "dpi": 96
+        }"##;
+        let p: TextBox = serde_json::from_str(src).unwrap();
+        let widths = p.possible_widths().collect::<Vec<i32>>();
+        assert_eq!(widths, vec![
+            Length::new(50.0, LengthUnit::Mm).unwrap().to_pango_units(p.unit_conversion()),
+            144 * SCALE
+        ]);
+        let heights = p.possible_heights().collect::<Vec<i32>>();
+        assert_eq!(heights.len(), 3);
+        let font_sizes = p.possible_font_sizes().collect::<Vec<i32>>();
+        assert_eq!(font_sizes, vec![10752, 12 * SCALE]);
+        let image = p.to_svg_image().unwrap();
+        assert!((image.width - 50.0 * 72.0 / 25.4).abs() < 0.01);
+        let points = p.width.clone().into_iter().collect::<Vec<u16>>();
+        assert_eq!(points, vec![142, 144]);
+        for dpi in ["0", "-96", "1e400"].iter() {
+            let bad = src.replace("\"dpi\": 96", &format!("\"dpi\": {}", dpi));
+            assert!(serde_json::from_str::<TextBox>(&bad).is_err());
+        }
+        let mut q = p.clone();
+        assert!(q.set_dpi(0.0).is_err());
+        assert_eq!(q.set_dpi(144.0).unwrap().dpi, 144.0);
+    }
+
     #[test]
     fn serde() {
         let t = "\"Times New Roman, bold\"";
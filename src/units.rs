@@ -0,0 +1,225 @@
+use crate::errors::SvgTextBoxError;
+use pango::SCALE;
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// The size of an em when no font size is known, in points
+pub const DEFAULT_EM: f64 = 12.0;
+/// The resolution used to convert pixels when none is given
+pub const DEFAULT_DPI: f64 = 72.0;
+
+/// `dpi` if it is a usable resolution, which is positive and finite
+pub(crate) fn valid_dpi(dpi: f64) -> Result<f64, SvgTextBoxError> {
+    if !dpi.is_finite() || dpi <= 0.0 {
+        return Err(SvgTextBoxError::InvalidLength(format!("{}dpi", dpi)));
+    }
+    Ok(dpi)
+}
+
+/// The units a length can be given in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LengthUnit {
+    Pt,
+    Px,
+    Mm,
+    Cm,
+    In,
+    Em,
+}
+
+/// The information needed to convert lengths to points
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UnitConversion {
+    /// pixels per inch
+    pub dpi: f64,
+    /// the size of an em, in points
+    pub em: f64,
+}
+
+impl Default for UnitConversion {
+    fn default() -> Self {
+        UnitConversion {
+            dpi: DEFAULT_DPI,
+            em: DEFAULT_EM
+        }
+    }
+}
+
+/// A positive length with a unit. Lengths without a unit are in points.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Length {
+    pub value: f64,
+    pub unit: LengthUnit,
+}
+
+impl Length {
+
+    pub fn new(value: f64, unit: LengthUnit) -> Result<Self, SvgTextBoxError> {
+        if !value.is_finite() || value <= 0.0 {
+            return Err(SvgTextBoxError::InvalidLength(value.to_string()));
+        }
+        Ok(Length {value, unit})
+    }
+
+    pub fn points(value: f64) -> Result<Self, SvgTextBoxError> {
+        Length::new(value, LengthUnit::Pt)
+    }
+
+    pub fn to_points(&self, conversion: UnitConversion) -> f64 {
+        match self.unit {
+            LengthUnit::Pt => self.value,
+            LengthUnit::Px => self.value * 72.0 / conversion.dpi,
+            LengthUnit::Mm => self.value * 72.0 / 25.4,
+            LengthUnit::Cm => self.value * 72.0 / 2.54,
+            LengthUnit::In => self.value * 72.0,
+            LengthUnit::Em => self.value * conversion.em,
+        }
+    }
+
+    /// This length in `points * pango::SCALE`
+    pub fn to_pango_units(&self, conversion: UnitConversion) -> i32 {
+        (self.to_points(conversion) * f64::from(SCALE)).round() as i32
+    }
+}
+
+impl FromStr for LengthUnit {
+    type Err = SvgTextBoxError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "" | "pt" => Ok(LengthUnit::Pt),
+            "px" => Ok(LengthUnit::Px),
+            "mm" => Ok(LengthUnit::Mm),
+            "cm" => Ok(LengthUnit::Cm),
+            "in" => Ok(LengthUnit::In),
+            "em" => Ok(LengthUnit::Em),
+            _ => Err(SvgTextBoxError::InvalidLength(s.to_string()))
+        }
+    }
+}
+
+impl FromStr for Length {
+    type Err = SvgTextBoxError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s.find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or_else(|| s.len());
+        let (value, unit) = s.split_at(split);
+        let value = value.parse::<f64>()
+            .map_err(|_| SvgTextBoxError::InvalidLength(s.to_string()))?;
+        let unit = unit.trim().parse::<LengthUnit>()?;
+        Length::new(value, unit)
+    }
+}
+
+impl fmt::Display for LengthUnit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            LengthUnit::Pt => "pt",
+            LengthUnit::Px => "px",
+            LengthUnit::Mm => "mm",
+            LengthUnit::Cm => "cm",
+            LengthUnit::In => "in",
+            LengthUnit::Em => "em",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl fmt::Display for Length {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.value, self.unit)
+    }
+}
+
+impl Serialize for Length {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Length {
+    fn deserialize<D>(deserializer: D) -> Result<Length, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(LengthVisitor)
+    }
+}
+
+struct LengthVisitor;
+
+impl<'de> Visitor<'de> for LengthVisitor {
+    type Value = Length;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a positive number of points, or a string of a positive number followed by one of pt, px, mm, cm, in or em")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        v.parse::<Length>()
+            .map_err(|_| de::Error::invalid_value(de::Unexpected::Str(v), &self))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        Length::points(v as f64)
+            .map_err(|_| de::Error::invalid_value(de::Unexpected::Unsigned(v), &self))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        Length::points(v as f64)
+            .map_err(|_| de::Error::invalid_value(de::Unexpected::Signed(v), &self))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+        Length::points(v)
+            .map_err(|_| de::Error::invalid_value(de::Unexpected::Float(v), &self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_lengths() {
+        assert_eq!("12".parse::<Length>().unwrap(), Length::points(12.0).unwrap());
+        assert_eq!("12.5mm".parse::<Length>().unwrap(), Length::new(12.5, LengthUnit::Mm).unwrap());
+        assert_eq!("2 in".parse::<Length>().unwrap(), Length::new(2.0, LengthUnit::In).unwrap());
+        assert_eq!("1.5EM".parse::<Length>().unwrap(), Length::new(1.5, LengthUnit::Em).unwrap());
+        assert!("0".parse::<Length>().is_err());
+        assert!("-3pt".parse::<Length>().is_err());
+        assert!("3furlongs".parse::<Length>().is_err());
+        assert!("mm".parse::<Length>().is_err());
+    }
+
+    #[test]
+    fn check_dpi() {
+        assert_eq!(valid_dpi(96.0).unwrap(), 96.0);
+        assert!(valid_dpi(0.0).is_err());
+        assert!(valid_dpi(-72.0).is_err());
+        assert!(valid_dpi(f64::INFINITY).is_err());
+    }
+
+    #[test]
+    fn convert_lengths() {
+        let c = UnitConversion {dpi: 144.0, em: 10.0};
+        assert_eq!(Length::new(1.0, LengthUnit::In).unwrap().to_points(c), 72.0);
+        assert_eq!(Length::new(2.54, LengthUnit::Cm).unwrap().to_points(c), 72.0);
+        assert_eq!(Length::new(144.0, LengthUnit::Px).unwrap().to_points(c), 72.0);
+        assert_eq!(Length::new(1.5, LengthUnit::Em).unwrap().to_points(c), 15.0);
+        assert_eq!(Length::points(1.5).unwrap().to_pango_units(c), 1536);
+    }
+
+    #[test]
+    fn length_serde() {
+        let l: Length = serde_json::from_str("\"25.4mm\"").unwrap();
+        assert_eq!(serde_json::to_string(&l).unwrap(), "\"25.4mm\"");
+        let l: Length = serde_json::from_str("7.5").unwrap();
+        assert_eq!(l, Length::points(7.5).unwrap());
+    }
+}
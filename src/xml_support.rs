@@ -3,9 +3,12 @@ use libxml::parser::{Parser};
 use libxml::xpath::Context;
 use libxml::tree::{Node, Document, Namespace};
 use std::collections::HashMap;
-use crate::textbox::{TextBox};
+use crate::textbox::{TextBox, TextBoxGroup, UnitContainer, Length, UnitConversion};
+use crate::units::valid_dpi;
+use crate::layout::{Shape, Exclusion, TextPath};
 use serde_json::{Value, json};
 use std::env;
+use std::path::Path;
 
 struct ConvertedTextBox {
 	prefix: String,
@@ -16,8 +19,62 @@ struct ConvertedTextBox {
 	height: f64
 }
 
-fn convert_textbox_src(tb: &Node, doc: &Document) -> Result<ConvertedTextBox, SvgTextBoxError> {
-	println!("converting...");
+/// A textbox read from a `textbox` element, not yet rendered
+struct TextBoxSource {
+	prefix: String,
+	x: u16,
+	y: u16,
+	group: Option<String>,
+	textbox: TextBox
+}
+
+impl TextBoxSource {
+
+	/// Render this textbox, fixing its font size to `font_size` if given
+	fn render(mut self, font_size: Option<f64>) -> Result<ConvertedTextBox, SvgTextBoxError> {
+		if let Some(f) = font_size {
+			self.textbox.set_font_size(UnitContainer::single(Length::points(f)?));
+		}
+		let textbox_standalone_svg = self.textbox.to_svg_image()?;
+		Ok(ConvertedTextBox {
+			x: self.x,
+			y: self.y,
+			prefix: self.prefix,
+			image: textbox_standalone_svg.src,
+			width: textbox_standalone_svg.width,
+			height: textbox_standalone_svg.height,
+		})
+	}
+}
+
+/// The shared font size of each group of textboxes
+fn group_font_sizes(sources: &[TextBoxSource]) -> Result<HashMap<String, f64>, SvgTextBoxError> {
+	let mut groups: HashMap<&str, Vec<TextBox>> = HashMap::new();
+	for src in sources {
+		if let Some(g) = &src.group {
+			groups.entry(g.as_str())
+				.or_insert_with(Vec::new)
+				.push(src.textbox.clone());
+		}
+	}
+	groups.into_iter()
+		.map(|(name, members)| -> Result<(String, f64), SvgTextBoxError> {
+			let size = TextBoxGroup::new(members).shared_font_size()?;
+			Ok((name.to_string(), size))
+		})
+		.collect()
+}
+
+/// Read a boolean attribute, which as in svg is either `true` or `false`
+fn parse_bool(value: &str) -> Result<bool, SvgTextBoxError> {
+	match value.trim() {
+		"true" => Ok(true),
+		"false" => Ok(false),
+		_ => Err(SvgTextBoxError::BadBoolParse(value.to_string()))
+	}
+}
+
+fn convert_textbox_src(tb: &Node, doc: &Document) -> Result<TextBoxSource, SvgTextBoxError> {
 	let mut attributes = tb.get_properties();
 	let markup_elem = tb.get_first_element_child()
 		.ok_or(SvgTextBoxError::MissingMarkup)?;
@@ -28,19 +85,18 @@ fn convert_textbox_src(tb: &Node, doc: &Document) -> Result<ConvertedTextBox, Sv
 
 	let mut map: HashMap<&str, Value> = HashMap::new();
 	map.insert("markup", Value::String(markup));
-	println!("inserted markup");
 
 
 	let width = attributes.remove("width");
 	let min_width = attributes.remove("min-width")
-		.map(|i| i.parse::<u16>())
-		.unwrap_or(Ok(100))?;
+		.map(|i| i.parse::<Length>())
+		.unwrap_or_else(|| Length::points(100.0))?;
 	let max_width = attributes.remove("max-width")
-		.map(|i| i.parse::<u16>())
-		.unwrap_or(Ok(1000))?;
+		.map(|i| i.parse::<Length>())
+		.unwrap_or_else(|| Length::points(1000.0))?;
 	let width_step = attributes.remove("width-step")
-		.map(|i| i.parse::<u16>())
-		.unwrap_or(Ok(10))?;
+		.map(|i| i.parse::<Length>())
+		.unwrap_or_else(|| Length::points(10.0))?;
 
 	let width = width
 		.map(|v| Value::String(v))
@@ -55,14 +111,14 @@ fn convert_textbox_src(tb: &Node, doc: &Document) -> Result<ConvertedTextBox, Sv
 
 	let height = attributes.remove("height");
 	let min_height = attributes.remove("min-height")
-		.map(|i| i.parse::<u16>())
-		.unwrap_or(Ok(100))?;
+		.map(|i| i.parse::<Length>())
+		.unwrap_or_else(|| Length::points(100.0))?;
 	let max_height = attributes.remove("max-height")
-		.map(|i| i.parse::<u16>())
-		.unwrap_or(Ok(1000))?;
+		.map(|i| i.parse::<Length>())
+		.unwrap_or_else(|| Length::points(1000.0))?;
 	let height_step = attributes.remove("height-step")
-		.map(|i| i.parse::<u16>())
-		.unwrap_or(Ok(10))?;
+		.map(|i| i.parse::<Length>())
+		.unwrap_or_else(|| Length::points(10.0))?;
 	let height = height
 		.map(|v| Value::String(v))
 		.unwrap_or({
@@ -76,14 +132,14 @@ fn convert_textbox_src(tb: &Node, doc: &Document) -> Result<ConvertedTextBox, Sv
 
 	let font_size = attributes.remove("font-size");
 	let min_font_size = attributes.remove("min-font-size")
-		.map(|i| i.parse::<u16>())
-		.unwrap_or(Ok(1))?;
+		.map(|i| i.parse::<Length>())
+		.unwrap_or_else(|| Length::points(1.0))?;
 	let max_font_size = attributes.remove("max-font-size")
-		.map(|i| i.parse::<u16>())
-		.unwrap_or(Ok(100))?;
+		.map(|i| i.parse::<Length>())
+		.unwrap_or_else(|| Length::points(100.0))?;
 	let font_size_step = attributes.remove("font-size-step")
-		.map(|i| i.parse::<u16>())
-		.unwrap_or(Ok(1))?;
+		.map(|i| i.parse::<Length>())
+		.unwrap_or_else(|| Length::points(1.0))?;
 	let font_size = font_size
 		.map(|v| Value::String(v))
 		.unwrap_or({
@@ -129,6 +185,64 @@ fn convert_textbox_src(tb: &Node, doc: &Document) -> Result<ConvertedTextBox, Sv
 		map.insert("font-desc", Value::String(f));
 	}
 
+	let mut conversion = UnitConversion::default();
+	if let Some(d) = attributes.remove("dpi") {
+		conversion.dpi = valid_dpi(d.parse::<f64>()?)?;
+		map.insert("dpi", json!(conversion.dpi));
+	}
+
+	for key in ["line-height", "paragraph-spacing", "rotation"].iter() {
+		if let Some(v) = attributes.remove(*key) {
+			map.insert(*key, json!(v.parse::<f64>()?));
+		}
+	}
+
+	// font files, separated by whitespace, each of which may be given an alias as `alias=path`
+	if let Some(f) = attributes.remove("fonts") {
+		let fonts = f.split_whitespace()
+			.map(|entry| match entry.find('=') {
+				Some(i) => json!({&entry[..i]: &entry[i + 1..]}),
+				None => Value::String(entry.to_string())
+			})
+			.collect::<Vec<Value>>();
+		map.insert("fonts", Value::Array(fonts));
+	}
+
+	for key in ["avoid-stranded-lines", "hyphenate"].iter() {
+		if let Some(a) = attributes.remove(*key) {
+			map.insert(*key, json!(parse_bool(&a)?));
+		}
+	}
+
+	// each of these sets the fit mode, so only one may be given
+	let fit_mode_keys = ["fit-mode", "max-lines", "min-lines"].iter()
+		.filter(|k| attributes.contains_key(**k))
+		.cloned()
+		.collect::<Vec<&str>>();
+	if fit_mode_keys.len() > 1 {
+		return Err(SvgTextBoxError::ConflictingAttributes(fit_mode_keys.join(", ")));
+	}
+	for key in ["max-lines", "min-lines"].iter() {
+		if let Some(n) = attributes.remove(*key) {
+			map.insert("fit-mode", json!({*key: n.parse::<u32>()?}));
+		}
+	}
+
+	let baseline = attributes.remove("baseline")
+		.map(|i| i.parse::<f64>())
+		.transpose()?;
+	// a baseline on its own implies baseline alignment
+	let alignment = match (attributes.remove("vertical-alignment"), baseline) {
+		(Some(ref v), None) if v == "baseline" => return Err(SvgTextBoxError::MissingBaseline),
+		(Some(ref v), Some(b)) if v == "baseline" => Some(json!({"baseline": b})),
+		(None, Some(b)) => Some(json!({"baseline": b})),
+		(Some(_), Some(_)) => return Err(SvgTextBoxError::ConflictingAttributes("baseline is only used with baseline alignment".to_string())),
+		(v, None) => v.map(Value::String)
+	};
+	if let Some(v) = alignment {
+		map.insert("vertical-alignment", v);
+	}
+
 	let x = attributes.remove("x")
 		.map(|i| i.parse::<u16>())
 		.unwrap_or(Ok(0))?;
@@ -136,34 +250,145 @@ fn convert_textbox_src(tb: &Node, doc: &Document) -> Result<ConvertedTextBox, Sv
 		.map(|i| i.parse::<u16>())
 		.unwrap_or(Ok(0))?;
 
+	// an outline to set the text inside, in the same coordinates as the textbox's position
+	if let Some(d) = attributes.remove("shape-path") {
+		let shape = Shape::from_svg_path(&d, f64::from(x), f64::from(y))?;
+		map.insert("shape", serde_json::to_value(shape)?);
+	}
+
+	// ids of elements elsewhere in the document for the text to flow around
+	if let Some(ids) = attributes.remove("exclusions") {
+		let exclusions = ids.split_whitespace()
+			.map(|id| {
+				let element = element_by_id(doc, id.trim_start_matches('#'))?;
+				exclusion_from_element(&element, f64::from(x), f64::from(y), conversion)
+			})
+			.collect::<Result<Vec<Exclusion>, SvgTextBoxError>>()?;
+		map.insert("exclusions", serde_json::to_value(exclusions)?);
+	}
+
+	// a path for the text to follow, as path data or the id of a `path` element,
+	// in the same coordinates as the textbox's position
+	let path_data = match attributes.remove("href") {
+		Some(id) => {
+			let element = element_by_id(doc, id.trim_start_matches('#'))?;
+			if element.get_name() != "path" {
+				return Err(SvgTextBoxError::BadReference(id));
+			}
+			element.get_property("d")
+		},
+		None => attributes.remove("path")
+	};
+	if let Some(d) = path_data {
+		let path = TextPath::from_svg_path(&d, f64::from(x), f64::from(y))?;
+		map.insert("path", serde_json::to_value(path)?);
+	}
+
 	let prefix = attributes.remove("__id")
 		.unwrap_or("textbox-00".to_string());
+	// ids inside each textbox's image must not clash with those of the others
+	map.insert("svg-id", Value::String(prefix.clone()));
+
+	let group = attributes.remove("group");
 
 	for (k, v) in attributes.iter() {
 		map.insert(k.as_str(), Value::String(v.to_string()));
 	}
 
 	let serialized = serde_json::to_string_pretty(&map)?;
-	println!("{}", &serialized);
 
 	let tb: TextBox = serde_json::from_str(&serialized)?;
 
-	println!("built tb");
-	println!("{:?}", tb);	
-
-	let textbox_standalone_svg = tb.to_svg_image()?;
-	
-	let out = ConvertedTextBox {
+	let out = TextBoxSource {
 		x,
 		y,
 		prefix,
-		image: textbox_standalone_svg.src,
-		width: textbox_standalone_svg.width,
-		height: textbox_standalone_svg.height,
+		group,
+		textbox: tb
 	};
 	Ok(out)
 }
 
+/// The element of `doc` whose id is `id`
+fn element_by_id(doc: &Document, id: &str) -> Result<Node, SvgTextBoxError> {
+	// xpath strings can't escape quotes, so the id is quoted with whichever it doesn't hold
+	let query = if !id.contains('\'') {
+		format!("//*[@id='{}']", id)
+	} else if !id.contains('"') {
+		format!("//*[@id=\"{}\"]", id)
+	} else {
+		return Err(SvgTextBoxError::BadReference(id.to_string()));
+	};
+	let mut context = Context::new(doc)
+		.map_err(|_| SvgTextBoxError::Xml)?;
+	context.findnodes(&query, None)
+		.map_err(|_| SvgTextBoxError::BadReference(id.to_string()))?
+		.into_iter()
+		.next()
+		.ok_or_else(|| SvgTextBoxError::BadReference(id.to_string()))
+}
+
+/// The area covered by `element`, relative to a textbox whose top left is at (x, y).
+/// Only the element's own geometry is read, so any transforms on it or its parents are ignored.
+fn exclusion_from_element(element: &Node, x: f64, y: f64, conversion: UnitConversion) -> Result<Exclusion, SvgTextBoxError> {
+	let number = |name: &str| -> Result<f64, SvgTextBoxError> {
+		let n = element.get_property(name)
+			.map(|v| coordinate(&v, conversion))
+			.transpose()?;
+		Ok(n.unwrap_or(0.0))
+	};
+	let exclusion = match element.get_name().as_str() {
+		"rect" | "image" | "use" => Exclusion::Rectangle {
+			x: number("x")? - x,
+			y: number("y")? - y,
+			width: number("width")?,
+			height: number("height")?
+		},
+		"circle" => Exclusion::Ellipse {
+			cx: number("cx")? - x,
+			cy: number("cy")? - y,
+			rx: number("r")?,
+			ry: number("r")?
+		},
+		"ellipse" => Exclusion::Ellipse {
+			cx: number("cx")? - x,
+			cy: number("cy")? - y,
+			rx: number("rx")?,
+			ry: number("ry")?
+		},
+		// polygon points read as path data are a move followed by implicit lines
+		"polygon" => {
+			let points = element.get_property("points").unwrap_or_default();
+			Exclusion::from_svg_path(&format!("M {} Z", points), x, y)?
+		},
+		"path" => {
+			let d = element.get_property("d").unwrap_or_default();
+			Exclusion::from_svg_path(&d, x, y)?
+		},
+		name => return Err(SvgTextBoxError::BadReference(name.to_string()))
+	};
+	Ok(exclusion)
+}
+
+/// A coordinate or size read as a length, which unlike a length may be zero or negative
+fn coordinate(value: &str, conversion: UnitConversion) -> Result<f64, SvgTextBoxError> {
+	let value = value.trim();
+	let (sign, magnitude) = match value.strip_prefix('-') {
+		Some(m) => (-1.0, m),
+		None => (1.0, value)
+	};
+	match magnitude.parse::<Length>() {
+		Ok(l) => Ok(sign * l.to_points(conversion)),
+		Err(e) => {
+			let number = magnitude.trim_end_matches(char::is_alphabetic).trim();
+			if number.parse::<f64>() == Ok(0.0) {
+				Ok(0.0)
+			} else {
+				Err(e)
+			}
+		}
+	}
+}
 
 fn find_textboxes(doc: &Document) -> Result<Vec<Node>, SvgTextBoxError> {
 	let mut context = Context::new(&doc)
@@ -188,6 +413,12 @@ fn find_textboxes(doc: &Document) -> Result<Vec<Node>, SvgTextBoxError> {
 
 /// transform `textbox` elements within xml markup
 pub fn transform_xml(src: &str) -> Result<String, SvgTextBoxError> {
+	transform_xml_in(src, Path::new(""))
+}
+
+/// transform `textbox` elements within xml markup from a document in `dir`,
+/// which relative font paths are resolved against
+pub fn transform_xml_in(src: &str, dir: &Path) -> Result<String, SvgTextBoxError> {
 	let parser = Parser::default();
 	let doc = parser.parse_string(src)?;
 	let input_stylesheet_source = include_str!("textbox_input.xslt");
@@ -202,8 +433,28 @@ pub fn transform_xml(src: &str) -> Result<String, SvgTextBoxError> {
 	let mut doc = input_stylesheet.transform(&doc)
 		.map_err(|_| SvgTextBoxError::XsltError)?;
 
+	let mut nodes = Vec::new();
+	let mut sources = Vec::new();
 	for mut node in find_textboxes(&doc)?.into_iter() {
-		let tb = convert_textbox_src(&node, &doc);
+		match convert_textbox_src(&node, &doc) {
+			Ok(mut src) => {
+				src.textbox.fonts.resolve_paths(dir);
+				nodes.push(node);
+				sources.push(src);
+			},
+			Err(SvgTextBoxError::MissingMarkup) => {
+				node.unlink();
+			},
+			Err(e) => return Err(e)
+		}
+	}
+	let group_font_sizes = group_font_sizes(&sources)?;
+
+	for (mut node, src) in nodes.into_iter().zip(sources.into_iter()) {
+		let font_size = src.group.as_ref()
+			.and_then(|g| group_font_sizes.get(g))
+			.cloned();
+		let tb = src.render(font_size);
 		match tb {
 			Ok(tb) => {
 				let mut n = Node::new("image", None, &doc)
@@ -226,9 +477,6 @@ pub fn transform_xml(src: &str) -> Result<String, SvgTextBoxError> {
 					.map_err(|_| SvgTextBoxError::Xml)?;
 				node.unlink();
 			},
-			Err(SvgTextBoxError::MissingMarkup) => {
-				node.unlink();
-			},
 			Err(e) => return Err(e)
 		}
 	}
@@ -238,6 +486,17 @@ pub fn transform_xml(src: &str) -> Result<String, SvgTextBoxError> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::layout::{VerticalAlignment, FitMode};
+    use crate::textbox::FontRegistry;
+    use crate::layout::{Shape, Exclusion, TextPath, WritingMode, TextDirection};
+
+    fn textboxes(svg: &str) -> Vec<Result<TextBox, SvgTextBoxError>> {
+        let doc = Parser::default().parse_string(svg).unwrap();
+        find_textboxes(&doc).unwrap()
+            .iter()
+            .map(|n| convert_textbox_src(n, &doc).map(|s| s.textbox))
+            .collect()
+    }
 
     #[test]
     fn test_transform_xml() {
@@ -260,4 +519,252 @@ mod tests {
         println!("{}", r);
         panic!();
     }
+
+    #[test]
+    fn test_transform_xml_group() {
+        let e = r#"
+			<svg width="400" height="200" xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink">
+				<textbox x="0" y="0" width="50" height="50" group="cards">
+					<markup>Hello World</markup>
+				</textbox>
+				<textbox x="100" y="0" width="300" height="150" group="cards">
+					<markup>Hello World</markup>
+				</textbox>
+			</svg>"#;
+        let parser = Parser::default();
+        let doc = parser.parse_string(e).unwrap();
+        let sources = find_textboxes(&doc).unwrap()
+            .iter()
+            .map(|n| convert_textbox_src(n, &doc).unwrap())
+            .collect::<Vec<TextBoxSource>>();
+        assert!(sources.iter().all(|s| s.group == Some("cards".to_string())));
+        let sizes = group_font_sizes(&sources).unwrap();
+        let narrow_alone = TextBoxGroup::new(vec![sources[0].textbox.clone()])
+            .shared_font_size()
+            .unwrap();
+        assert_eq!(sizes.get("cards"), Some(&narrow_alone));
+        assert!(transform_xml(e).is_ok());
+    }
+
+    #[test]
+    fn test_vertical_alignment_attribute() {
+        let e = r#"
+			<svg width="400" height="200" xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink">
+				<textbox width="200" height="100" vertical-alignment="bottom">
+					<markup>Hello World</markup>
+				</textbox>
+				<textbox width="200" height="100" vertical-alignment="baseline" baseline="24">
+					<markup>Hello World</markup>
+				</textbox>
+				<textbox width="200" height="100" baseline="30">
+					<markup>Hello World</markup>
+				</textbox>
+			</svg>"#;
+        let alignments = textboxes(e).into_iter()
+            .map(|r| r.unwrap().vertical_alignment)
+            .collect::<Vec<VerticalAlignment>>();
+        assert_eq!(alignments, vec![VerticalAlignment::Bottom, VerticalAlignment::Baseline(24.0), VerticalAlignment::Baseline(30.0)]);
+
+        let e = r#"<svg xmlns="http://www.w3.org/2000/svg"><textbox width="200" height="100" vertical-alignment="baseline"><markup>Hello World</markup></textbox></svg>"#;
+        match textboxes(e).remove(0) {
+            Err(SvgTextBoxError::MissingBaseline) => (),
+            r => panic!("expected a missing baseline error, got {:?}", r)
+        }
+
+        let e = r#"<svg xmlns="http://www.w3.org/2000/svg"><textbox width="200" height="100" vertical-alignment="bottom" baseline="24"><markup>Hello World</markup></textbox></svg>"#;
+        match textboxes(e).remove(0) {
+            Err(SvgTextBoxError::ConflictingAttributes(_)) => (),
+            r => panic!("expected conflicting attributes, got {:?}", r)
+        }
+    }
+
+    #[test]
+    fn test_spacing_attributes() {
+        let e = r#"
+			<svg width="400" height="200" xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink">
+				<textbox width="200" height="100" line-height="1.25" letter-spacing="1mm" paragraph-spacing="0.5">
+					<markup>Hello World</markup>
+				</textbox>
+				<textbox width="30" height="200" rotation="270" writing-mode="vertical" direction="rtl">
+					<markup>Hello World</markup>
+				</textbox>
+			</svg>"#;
+        let mut boxes = textboxes(e);
+        let turned = boxes.remove(1).unwrap();
+        assert_eq!(turned.rotation, 270.0);
+        assert_eq!(turned.writing_mode, WritingMode::Vertical);
+        assert_eq!(turned.direction, TextDirection::Rtl);
+        let tb = boxes.remove(0).unwrap();
+        assert_eq!(tb.line_height, 1.25);
+        assert_eq!(tb.letter_spacing, Some("1mm".parse::<Length>().unwrap()));
+        assert_eq!(tb.paragraph_spacing, 0.5);
+        assert_eq!(tb.rotation, 0.0);
+    }
+
+    #[test]
+    fn test_fit_mode_attributes() {
+        let e = r#"
+			<svg width="400" height="200" xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink">
+				<textbox width="200" height="100" fit-mode="single-line">
+					<markup>Hello World</markup>
+				</textbox>
+				<textbox width="200" height="100" max-lines="2" avoid-stranded-lines="true">
+					<markup>Hello World</markup>
+				</textbox>
+			</svg>"#;
+        let boxes = textboxes(e).into_iter()
+            .map(Result::unwrap)
+            .collect::<Vec<TextBox>>();
+        assert_eq!(boxes[0].fit_mode, FitMode::SingleLine);
+        assert_eq!(boxes[0].svg_id.as_deref(), Some("textbox-00"));
+        assert!(!boxes[0].avoid_stranded_lines);
+        assert_eq!(boxes[1].fit_mode, FitMode::MaxLines(2));
+        assert!(boxes[1].avoid_stranded_lines);
+        assert!(parse_bool("true").unwrap());
+        assert!(!parse_bool(" false ").unwrap());
+        for other in ["1", "True", "yes", "FALSE", "off", "maybe"].iter() {
+            assert!(parse_bool(other).is_err());
+        }
+
+        let e = r#"<svg xmlns="http://www.w3.org/2000/svg"><textbox width="200" height="100" max-lines="3" min-lines="2"><markup>Hello World</markup></textbox></svg>"#;
+        match textboxes(e).remove(0) {
+            Err(SvgTextBoxError::ConflictingAttributes(_)) => (),
+            r => panic!("expected conflicting attributes, got {:?}", r)
+        }
+    }
+
+    #[test]
+    fn test_hyphenation_attributes() {
+        let e = r#"
+			<svg width="400" height="200" xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink">
+				<textbox width="200" height="100" lang="nl" hyphenate="true">
+					<markup>Verzekeringsmaatschappij</markup>
+				</textbox>
+			</svg>"#;
+        let textbox = textboxes(e).remove(0).unwrap();
+        assert_eq!(textbox.lang, Some("nl".to_string()));
+        assert!(textbox.hyphenate);
+    }
+
+    #[test]
+    fn test_fonts_attribute() {
+        let e = r#"
+			<svg width="400" height="200" xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink">
+				<textbox width="200" height="100" fonts="fonts/ brand=fonts/Brand-Bold.otf" font-desc="brand">
+					<markup>Hello World</markup>
+				</textbox>
+			</svg>"#;
+        let textbox = textboxes(e).remove(0).unwrap();
+        let mut expected = FontRegistry::new();
+        expected.add_path("fonts/")
+            .add_alias("brand", "fonts/Brand-Bold.otf");
+        assert_eq!(textbox.fonts, expected);
+        // relative paths are found next to the document
+        if cfg!(feature = "fontconfig") {
+            match transform_xml_in(e, Path::new("/no/such/dir")) {
+                Err(SvgTextBoxError::FontFile(p)) => assert!(p.starts_with("/no/such/dir/")),
+                _ => panic!("expected the fonts to be looked for in the document's directory")
+            }
+        }
+    }
+
+    #[test]
+    fn test_shape_attributes() {
+        let e = r#"
+			<svg width="400" height="200" xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink">
+				<textbox width="200" height="100" shape="ellipse">
+					<markup>Hello World</markup>
+				</textbox>
+				<textbox x="10" y="20" width="100" height="100" shape-path="M 10 20 H 110 L 60 120 Z">
+					<markup>Hello World</markup>
+				</textbox>
+			</svg>"#;
+        let boxes = textboxes(e).into_iter()
+            .map(Result::unwrap)
+            .collect::<Vec<TextBox>>();
+        assert_eq!(boxes[0].shape, Shape::Ellipse);
+        assert_eq!(boxes[1].shape, Shape::Polygon(vec![(0.0, 0.0), (100.0, 0.0), (50.0, 100.0)]));
+    }
+
+    #[test]
+    fn test_exclusions_attribute() {
+        let e = r#"
+			<svg width="400" height="200" xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink">
+				<rect id="logo" x="160" y="10" width="40" height="40"/>
+				<circle id="seal" cx="30" cy="80" r="10"/>
+				<textbox x="10" y="10" width="200" height="100" exclusions="logo #seal">
+					<markup>Hello World</markup>
+				</textbox>
+				<textbox width="200" height="100" exclusions="missing">
+					<markup>Hello World</markup>
+				</textbox>
+			</svg>"#;
+        let mut boxes = textboxes(e);
+        let textbox = boxes.remove(0).unwrap();
+        assert_eq!(textbox.exclusions, vec![
+            Exclusion::Rectangle {x: 150.0, y: 0.0, width: 40.0, height: 40.0},
+            Exclusion::Ellipse {cx: 20.0, cy: 70.0, rx: 10.0, ry: 10.0},
+        ]);
+        match boxes.remove(0) {
+            Err(SvgTextBoxError::BadReference(id)) => assert_eq!(id, "missing"),
+            _ => panic!("expected a missing reference")
+        }
+    }
+
+    #[test]
+    fn test_exclusion_lengths() {
+        let e = r#"
+			<svg width="400" height="200" xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink">
+				<rect id="logo" x="20px" y="0" width="1in" height="0.5cm"/>
+				<rect id="it's" x="10" y="10" width="10" height="10"/>
+				<textbox x="10" y="10" width="200" height="100" dpi="144" exclusions="logo">
+					<markup>Hello World</markup>
+				</textbox>
+				<textbox x="10" y="10" width="200" height="100" exclusions="it's">
+					<markup>Hello World</markup>
+				</textbox>
+				<textbox width="200" height="100" exclusions="x']|//*[@id='logo">
+					<markup>Hello World</markup>
+				</textbox>
+			</svg>"#;
+        let mut boxes = textboxes(e);
+        let textbox = boxes.remove(0).unwrap();
+        match textbox.exclusions[0] {
+            Exclusion::Rectangle {x, y, width, height} => {
+                assert_eq!((x, y, width), (0.0, -10.0, 72.0));
+                assert!((height - 72.0 / 2.54 / 2.0).abs() < 1e-9);
+            },
+            _ => panic!("expected a rectangle")
+        }
+        let textbox = boxes.remove(0).unwrap();
+        assert_eq!(textbox.exclusions, vec![Exclusion::Rectangle {x: 0.0, y: 0.0, width: 10.0, height: 10.0}]);
+        // an id can't end the query early to pick out another element
+        assert!(boxes.remove(0).is_err());
+        assert!(coordinate("10pc", UnitConversion::default()).is_err());
+    }
+
+    #[test]
+    fn test_path_attributes() {
+        let e = r##"
+			<svg width="400" height="200" xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink">
+				<path id="arc" d="M 20 120 A 90 90 0 0 1 200 120" fill="none"/>
+				<textbox x="10" y="20" width="200" height="30" href="#arc">
+					<markup>Hello World</markup>
+				</textbox>
+				<textbox x="10" y="20" width="200" height="30" path="M 20 120 A 90 90 0 0 1 200 120">
+					<markup>Hello World</markup>
+				</textbox>
+				<textbox width="200" height="30" href="#seal">
+					<markup>Hello World</markup>
+				</textbox>
+				<circle id="seal" cx="30" cy="80" r="10"/>
+			</svg>"##;
+        let mut boxes = textboxes(e);
+        let expected = TextPath::new("M 10 100 A 90 90 0 0 1 190 100").unwrap();
+        for textbox in boxes.drain(..2) {
+            let length = textbox.unwrap().path.unwrap().length();
+            assert!((length - expected.length()).abs() < 1e-6);
+        }
+        assert!(boxes.remove(0).is_err());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,62 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+/// Run svgtextbox with `args`, giving it `input` on stdin
+fn run(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_svgtextbox"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // a command refused before reading its input may already have closed stdin
+    let _ = child.stdin.take().unwrap().write_all(input.as_bytes());
+    child.wait_with_output().unwrap()
+}
+
+static TEXTBOX: &str = r#"{"markup": "Hello World", "width": 200, "height": 100}"#;
+
+#[test]
+fn render_writes_each_format() {
+    let svg = run(&["render", "-"], TEXTBOX);
+    assert!(svg.status.success());
+    assert!(String::from_utf8(svg.stdout).unwrap().contains("<svg"));
+
+    let pdf = run(&["render", "-", "--format", "pdf"], TEXTBOX);
+    assert!(pdf.status.success());
+    assert!(pdf.stdout.starts_with(b"%PDF"));
+
+    let png = run(&["render", "-", "--format", "png", "--dpi", "144"], TEXTBOX);
+    assert!(png.status.success());
+    assert!(png.stdout.starts_with(b"\x89PNG"));
+}
+
+#[test]
+fn fit_reports_the_chosen_size() {
+    let output = run(&["fit", "-"], TEXTBOX);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines = stdout.lines().collect::<Vec<&str>>();
+    assert_eq!(lines[0], "width: 200");
+    assert_eq!(lines[1], "height: 100");
+    assert!(lines[2].starts_with("font-size: "));
+    assert!(!stdout.contains("truncated"));
+}
+
+#[test]
+fn failures_give_their_exit_codes() {
+    let too_long = r#"{
+        "markup": "An extremely long product name which could never fit in such a small box",
+        "width": 20,
+        "height": 10,
+        "font-size": {"min": 40, "max": 50}
+    }"#;
+    let output = run(&["fit", "-"], too_long);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(output.stdout.is_empty());
+
+    assert_eq!(run(&["fit", "-"], "{").status.code(), Some(5));
+    assert_eq!(run(&["render", "-", "--format", "gif"], TEXTBOX).status.code(), Some(2));
+    assert_eq!(run(&["scribble"], "").status.code(), Some(2));
+}
@@ -0,0 +1,26 @@
+use libxml::parser::Parser;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+#[test]
+fn transform_writes_only_svg() {
+    let src = r#"<svg width="200" height="200" xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink">
+        <textbox x="0" y="0" width="200" height="200">
+            <markup>Hello World</markup>
+        </textbox>
+    </svg>"#;
+    let mut child = Command::new(env!("CARGO_BIN_EXE_svgtextbox"))
+        .arg("transform")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(src.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let doc = Parser::default().parse_string(&stdout).unwrap();
+    let root = doc.get_root_element().unwrap();
+    assert_eq!(root.get_name(), "svg");
+    assert!(stdout.contains("<image"));
+}